@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+use crate::union_find::UnionFind;
+
+/// Faces with less area than this are reported as degenerate
+pub(crate) const DEGENERATE_AREA: f32 = 1e-10;
+
+/**
+ * Problems found by Mesh::analyze(). Edges are given as pairs of vertex
+ * indices with the lower index first.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshReport {
+    /// Edges used by only one face, i.e. holes in the surface
+    pub boundary_edges: Vec<[usize; 2]>,
+    /// Edges shared by more than two faces
+    pub non_manifold_edges: Vec<[usize; 2]>,
+    /// Vertices where separate fans of faces touch, like a bowtie
+    pub non_manifold_vertices: Vec<usize>,
+    /// Neighboring faces that traverse their shared edge in the same
+    /// direction, so one of them is flipped
+    pub inconsistent_faces: Vec<[usize; 2]>,
+    /// Faces with repeated vertices or no area
+    pub degenerate_faces: Vec<usize>,
+    /// Vertices not used by any face
+    pub unreferenced_vertices: Vec<usize>,
+}
+
+impl MeshReport {
+    /// Whether the surface is closed, i.e. every edge has exactly two faces
+    pub fn is_watertight(&self) -> bool {
+        self.boundary_edges.is_empty() && self.non_manifold_edges.is_empty()
+    }
+
+    /// Whether no problems were found at all
+    pub fn is_clean(&self) -> bool {
+        self.is_watertight()
+            && self.non_manifold_vertices.is_empty()
+            && self.inconsistent_faces.is_empty()
+            && self.degenerate_faces.is_empty()
+            && self.unreferenced_vertices.is_empty()
+    }
+}
+
+impl Display for MeshReport {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "boundary edges:          {}", self.boundary_edges.len())?;
+        writeln!(f, "non-manifold edges:      {}", self.non_manifold_edges.len())?;
+        writeln!(f, "non-manifold vertices:   {}",
+            self.non_manifold_vertices.len())?;
+        writeln!(f, "inconsistent windings:   {}", self.inconsistent_faces.len())?;
+        writeln!(f, "degenerate faces:        {}", self.degenerate_faces.len())?;
+        writeln!(f, "unreferenced vertices:   {}",
+            self.unreferenced_vertices.len())?;
+        write!(f, "watertight:              {}",
+            if self.is_watertight() { "yes" } else { "no" })
+    }
+}
+
+impl Mesh {
+    /**
+     * Check whether the mesh is a closed, consistently wound manifold, as
+     * needed for 3D printing. Connectivity is by vertex index, so an
+     * unwelded grid shows every tile seam as boundary edges.
+     */
+    pub fn analyze(&self) -> MeshReport {
+        let mut report = MeshReport::default();
+
+        // Every face using each edge, and whether it goes from the lower
+        // vertex index to the higher one
+        let mut edges: HashMap<[usize; 2], Vec<(usize, bool)>> = HashMap::new();
+        let mut edge_order: Vec<[usize; 2]> = Vec::new();
+        let mut references = vec![0usize; self.vertices.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            let indices = face.indices();
+            let mut repeated = false;
+            for (i, a) in indices.iter().enumerate() {
+                let b = indices[(i + 1) % indices.len()];
+                references[*a] += 1;
+                if indices[..i].contains(a) {
+                    repeated = true;
+                }
+                if *a == b {
+                    continue;
+                }
+
+                let key = [*a.min(&b), *a.max(&b)];
+                let uses = edges.entry(key).or_insert_with(|| {
+                    edge_order.push(key);
+                    Vec::new()
+                });
+                uses.push((f, *a < b));
+            }
+
+            if repeated || self.face_area(f) < DEGENERATE_AREA {
+                report.degenerate_faces.push(f);
+            }
+        }
+
+        // Corners of faces around the same vertex are joined across each
+        // manifold edge. A vertex whose corners don't all end up joined is
+        // where separate surfaces touch
+        let mut corner_offsets = Vec::with_capacity(self.faces.len());
+        let mut corner_count = 0;
+        for face in self.faces.iter() {
+            corner_offsets.push(corner_count);
+            corner_count += face.len();
+        }
+        let corner = |face: usize, vertex: usize| {
+            let position = self.faces[face].indices().iter()
+                .position(|v| *v == vertex)
+                .unwrap();
+            corner_offsets[face] + position
+        };
+        let mut fans = UnionFind::new(corner_count);
+
+        for key in edge_order {
+            let uses = &edges[&key];
+            match uses[..] {
+                [_] => report.boundary_edges.push(key),
+                [(f, f_forward), (g, g_forward)] => {
+                    if f_forward == g_forward {
+                        report.inconsistent_faces.push([f, g]);
+                    }
+                    for vertex in key.iter() {
+                        fans.union(corner(f, *vertex), corner(g, *vertex));
+                    }
+                },
+                _ => report.non_manifold_edges.push(key)
+            }
+        }
+
+        let mut fan_roots: Vec<Option<usize>> = vec![None; self.vertices.len()];
+        let mut non_manifold = vec![false; self.vertices.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            for (i, vertex) in face.indices().iter().enumerate() {
+                let root = fans.find(corner_offsets[f] + i);
+                match fan_roots[*vertex] {
+                    None => fan_roots[*vertex] = Some(root),
+                    Some(existing) if existing != root
+                        => non_manifold[*vertex] = true,
+                    _ => {}
+                }
+            }
+        }
+        report.non_manifold_vertices = (0..self.vertices.len())
+            .filter(|v| non_manifold[*v])
+            .collect();
+
+        report.unreferenced_vertices = (0..self.vertices.len())
+            .filter(|v| references[*v] == 0)
+            .collect();
+
+        report
+    }
+
+    /// The area of a face, which is half the length of its Newell normal
+    pub fn face_area(&self, face_index: usize) -> f32 {
+        let indices = self.faces[face_index].indices();
+        let mut normal = Vertex([0.0, 0.0, 0.0]);
+        for (i, current) in indices.iter().enumerate() {
+            let next = indices[(i + 1) % indices.len()];
+            normal = normal.add(
+                &self.vertices[*current].cross(&self.vertices[next]));
+        }
+
+        0.5 * normal.length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::Face;
+    use crate::test_meshes::cube;
+
+    #[test]
+    fn cube_is_clean() {
+        let report = cube().analyze();
+        assert_eq!(report, MeshReport::default());
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn missing_face_leaves_boundary_edges() {
+        let mut mesh = cube();
+        mesh.faces.pop();
+        mesh.face_attributes.pop();
+
+        let report = mesh.analyze();
+        assert_eq!(report.boundary_edges.len(), 4);
+        assert!(!report.is_watertight());
+    }
+
+    #[test]
+    fn flipped_face_is_inconsistent_with_its_neighbors() {
+        let mut mesh = cube();
+        if let Face::Quad(corners) = &mut mesh.faces[0] {
+            corners.reverse();
+        }
+
+        let report = mesh.analyze();
+        assert!(report.is_watertight());
+        assert_eq!(report.inconsistent_faces.len(), 4);
+        assert!(report.inconsistent_faces.iter().all(|pair| pair.contains(&0)));
+    }
+
+    #[test]
+    fn fin_on_an_edge_is_non_manifold() {
+        let mut mesh = cube();
+        mesh.vertices.push(Vertex([-1.0, 0.0, 0.5]));
+        mesh.push_face(Face::Triangle([0, 4, 8]), Default::default());
+
+        let report = mesh.analyze();
+        assert_eq!(report.non_manifold_edges, vec![[0, 4]]);
+        assert_eq!(report.boundary_edges.len(), 2);
+    }
+
+    #[test]
+    fn bowtie_vertex_is_non_manifold() {
+        let mesh = Mesh::from_geometry(
+            vec![
+                Vertex([0.0, 0.0, 0.0]),
+                Vertex([1.0, 0.0, 0.0]),
+                Vertex([1.0, 1.0, 0.0]),
+                Vertex([-1.0, 0.0, 0.0]),
+                Vertex([-1.0, -1.0, 0.0]),
+            ],
+            vec![Face::Triangle([0, 1, 2]), Face::Triangle([0, 3, 4])]);
+
+        assert_eq!(mesh.analyze().non_manifold_vertices, vec![0]);
+    }
+
+    #[test]
+    fn degenerate_faces_and_unused_vertices_are_found() {
+        let mut mesh = cube();
+        mesh.vertices.push(Vertex([0.5, 0.0, 0.0]));
+        mesh.vertices.push(Vertex([2.0, 2.0, 2.0]));
+        // No area, and a repeated corner
+        mesh.push_face(Face::Triangle([0, 8, 1]), Default::default());
+        mesh.push_face(Face::Triangle([0, 1, 1]), Default::default());
+
+        let report = mesh.analyze();
+        assert_eq!(report.degenerate_faces, vec![6, 7]);
+        assert_eq!(report.unreferenced_vertices, vec![9]);
+    }
+}
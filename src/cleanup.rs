@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::mesh::{Mesh, remove_repeated_corners};
+use crate::analysis::DEGENERATE_AREA;
+use crate::parts::position_ids;
+
+/// What Mesh::cleanup() removed. Indices are into the original mesh
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CleanupReport {
+    /// Faces that use a vertex twice or have no area
+    pub degenerate_faces: Vec<usize>,
+    /// Copies of an earlier face with the same winding
+    pub duplicate_faces: Vec<usize>,
+    /// Pairs of faces on the same vertices with opposite windings, like
+    /// the walls where two tiles overlap. Both faces of a pair are removed
+    pub opposing_faces: Vec<[usize; 2]>,
+    /// Vertices no face used after the faces were removed
+    pub unreferenced_vertices: Vec<usize>,
+}
+
+impl CleanupReport {
+    /// Whether nothing was removed
+    pub fn is_empty(&self) -> bool {
+        self.degenerate_faces.is_empty()
+            && self.duplicate_faces.is_empty()
+            && self.opposing_faces.is_empty()
+            && self.unreferenced_vertices.is_empty()
+    }
+}
+
+impl Display for CleanupReport {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "removed {} degenerate faces, {} duplicate faces, {} opposing \
+            face pairs and {} unreferenced vertices",
+            self.degenerate_faces.len(),
+            self.duplicate_faces.len(),
+            self.opposing_faces.len(),
+            self.unreferenced_vertices.len())
+    }
+}
+
+impl Mesh {
+    /**
+     * Remove faces that don't contribute to the surface and vertices no
+     * face uses, and report what was removed.
+     *
+     * Corners that repeat the previous corner are dropped first, so a quad
+     * with a collapsed edge becomes a triangle. Faces that still use a
+     * vertex twice, or have no area, are removed. Faces on the same
+     * vertices as an earlier face are removed too. If they are wound the
+     * other way, the two faces are back to back, like where overlapping
+     * tiles meet, so both are removed.
+     *
+     * Faces are compared by vertex position, so duplicates between
+     * unwelded tiles are found too. Vertices keep their order.
+     */
+    pub fn cleanup(&self) -> (Self, CleanupReport) {
+        let mut report = CleanupReport::default();
+        let positions = position_ids(&self.vertices);
+
+        let mut faces = Vec::with_capacity(self.faces.len());
+        let pairs = self.faces.iter().zip(self.face_attributes.iter());
+        for (f, (face, attributes)) in pairs.enumerate() {
+            let kept = remove_repeated_corners(face, attributes).filter(|(face, _)| {
+                let indices = face.indices();
+                let distinct = (1..indices.len())
+                    .all(|i| !indices[..i].contains(&indices[i]));
+                distinct && self.face_area(f) >= DEGENERATE_AREA
+            });
+
+            if kept.is_none() {
+                report.degenerate_faces.push(f);
+            }
+            faces.push(kept);
+        }
+
+        // Faces on the same positions get the same key no matter where they
+        // start or which way they go. Each key maps to the face kept for it
+        // and whether that one went the other way
+        let mut kept: HashMap<Vec<usize>, (usize, bool)> = HashMap::new();
+        for f in 0..faces.len() {
+            let corners: Vec<usize> = match &faces[f] {
+                Some((face, _)) => face.indices().iter().map(|v| positions[*v]).collect(),
+                None => continue
+            };
+            let forward = canonical_rotation(corners.iter().copied());
+            let backward = canonical_rotation(corners.iter().rev().copied());
+            let reversed = backward < forward;
+            let key = if reversed { backward } else { forward };
+
+            match kept.get(&key).copied() {
+                Some((other, other_reversed)) if other_reversed != reversed => {
+                    report.opposing_faces.push([other, f]);
+                    faces[other] = None;
+                    faces[f] = None;
+                    kept.remove(&key);
+                },
+                Some(_) => {
+                    report.duplicate_faces.push(f);
+                    faces[f] = None;
+                },
+                None => {
+                    kept.insert(key, (f, reversed));
+                }
+            }
+        }
+
+        let mut used = vec![false; self.vertices.len()];
+        for (face, _) in faces.iter().flatten() {
+            for vertex in face.indices().iter() {
+                used[*vertex] = true;
+            }
+        }
+
+        let mut new_indices: Vec<usize> = Vec::with_capacity(self.vertices.len());
+        let mut vertices = Vec::new();
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            new_indices.push(vertices.len());
+            if used[v] {
+                vertices.push(*vertex);
+            } else {
+                report.unreferenced_vertices.push(v);
+            }
+        }
+
+        let mut result = Self {
+            vertices,
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..self.clone()
+        };
+        for (face, attributes) in faces.into_iter().flatten() {
+            result.push_face(face.map(|v| new_indices[v]), attributes);
+        }
+
+        (result, report)
+    }
+}
+
+/**
+ * The corners rotated to start at the lowest one, so the same loop of
+ * corners gives the same list wherever it started
+ */
+fn canonical_rotation<I>(corners: I) -> Vec<usize>
+        where I: Iterator<Item = usize> {
+    let mut corners: Vec<usize> = corners.collect();
+    let start = (0..corners.len()).min_by_key(|i| corners[*i]).unwrap_or(0);
+    corners.rotate_left(start);
+    corners
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Vertex, Face};
+    use crate::mesh::FaceAttributes;
+    use crate::test_meshes::{cube, unwelded_cube};
+
+    fn assert_same_geometry(actual: &Mesh, expected: &Mesh) {
+        assert_eq!(actual.vertices(), expected.vertices());
+        assert_eq!(actual.faces(), expected.faces());
+    }
+
+    fn with_face(mut mesh: Mesh, face: Face) -> Mesh {
+        mesh.push_face(face, FaceAttributes::default());
+        mesh
+    }
+
+    #[test]
+    fn clean_cube_is_left_alone() {
+        let (cleaned, report) = cube().cleanup();
+        assert!(report.is_empty());
+        assert_same_geometry(&cleaned, &cube());
+    }
+
+    #[test]
+    fn duplicate_faces_are_removed() {
+        let mesh = with_face(cube(), Face::Quad([4, 6, 2, 0]));
+        let (cleaned, report) = mesh.cleanup();
+        assert_eq!(report.duplicate_faces, vec![6]);
+        assert_same_geometry(&cleaned, &cube());
+    }
+
+    #[test]
+    fn opposing_faces_are_both_removed() {
+        let mesh = with_face(cube(), Face::Quad([0, 2, 6, 4]));
+        let (cleaned, report) = mesh.cleanup();
+        assert_eq!(report.opposing_faces, vec![[0, 6]]);
+        assert_eq!(cleaned.faces(), &cube().faces()[1..]);
+        assert_eq!(cleaned.vertices().len(), 8);
+    }
+
+    #[test]
+    fn degenerate_faces_are_removed() {
+        let mut mesh = cube();
+        mesh.vertices.push(Vertex([2.0, 0.0, 0.0]));
+        let mesh = with_face(mesh, Face::Triangle([0, 1, 8]));
+        let mesh = with_face(mesh, Face::Triangle([0, 1, 1]));
+
+        let (cleaned, report) = mesh.cleanup();
+        assert_eq!(report.degenerate_faces, vec![6, 7]);
+        assert_eq!(report.unreferenced_vertices, vec![8]);
+        assert_same_geometry(&cleaned, &cube());
+    }
+
+    #[test]
+    fn collapsed_edges_leave_a_triangle() {
+        let mut mesh = cube();
+        mesh.faces[0] = Face::Quad([0, 4, 4, 6]);
+        let (cleaned, report) = mesh.cleanup();
+        assert!(report.is_empty());
+        assert_eq!(cleaned.faces()[0], Face::Triangle([0, 4, 6]));
+    }
+
+    #[test]
+    fn duplicates_are_found_between_unwelded_faces() {
+        let mut mesh = unwelded_cube();
+        let copy = unwelded_cube().extract_faces(&[0]);
+        mesh.add_geometry(&copy);
+
+        let (cleaned, report) = mesh.cleanup();
+        assert_eq!(report.duplicate_faces, vec![6]);
+        assert_eq!(report.unreferenced_vertices, (24..28).collect::<Vec<_>>());
+        assert_same_geometry(&cleaned, &unwelded_cube());
+    }
+}
@@ -0,0 +1,831 @@
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+
+use celtic_knots_3d::{
+    Contouring, FileFormat, Grouping, RemeshOptions, SmoothOptions, Subdivision,
+    Transform, Triangulation
+};
+use celtic_knots_3d::primitives::Vertex;
+use celtic_knots_3d::color::{Color, parse_hex_color, default_palette};
+use celtic_knots_3d::weld::DEFAULT_WELD_TOLERANCE;
+use celtic_knots_3d::normals::DEFAULT_CREASE_ANGLE;
+use celtic_knots_3d::tileset::{TILE_NAMES, format_path};
+
+pub const USAGE: &str = "\
+Usage: celtic-knots-3d <command> [options]
+
+Commands:
+    generate    Generate a grid of knot tiles and save it as a mesh
+    check       Check whether meshes are closed and manifold. Give OBJ files
+                to check, or options for generate to check a generated grid.
+                Exits with status 1 if any problems are found
+    measure     Print the size, area and volume of meshes, and how much
+                filament they take to print. Takes files or options like check
+    help        Print this message
+
+Usage of check: celtic-knots-3d check [FILE.obj...] [options for generate]
+                                      [--intersections]
+Usage of measure: celtic-knots-3d measure [FILE.obj...] [options for generate]
+                                          [--density <G/CM3>]
+
+Options for check:
+        --intersections       Also look for faces that cut through each
+                              other, like strands colliding at a twist
+
+Options for measure:
+        --density <G/CM3>     Density of the filament (default 1.24, PLA).
+                              Units are scaled to millimeters by --cell-size
+
+Options for generate:
+    -s, --size <N>x<M>x<P>    Grid dimensions in cells (default 5x5x5).
+                              A single number makes a cube, e.g. --size 7
+    -t, --tileset <NAME|DIR>  Tileset name under data/ or a directory
+                              containing the tile OBJ files (default sturdy)
+        --mirrors             Randomly replace twists with mirrors
+        --mirror-chance <P>   Probability in [0, 1] that a twist cell becomes
+                              a mirror when --mirrors is on (default 0.78)
+        --seed <SEED>         Seed for the random number generator
+        --weld                Merge duplicate vertices where cells meet
+        --weld-tolerance <T>  Distance within which vertices are merged,
+                              in cells. Implies --weld (default 0.0001)
+        --cleanup             Remove degenerate and duplicate faces, and
+                              faces back to back where tiles overlap, and
+                              say what was removed
+        --orient              Fix inconsistent winding so all faces point
+                              outward. Use with --weld
+        --remesh <VOXELS>     Rebuild the surface from a grid with this many
+                              voxels per cell, which merges overlapping
+                              tiles into one closed solid
+        --contouring <HOW>    Build the surface for --remesh with
+                              marching-cubes or dual (dual contouring, which
+                              keeps sharp edges but can leave non-manifold
+                              spots). Default marching-cubes
+        --smooth-union <R>    Round off creases where strands meet with this
+                              radius in cells when remeshing. Gaps narrower
+                              than twice this are closed
+        --scale <S|SX,SY,SZ>  Scale the knot, either evenly or along each
+                              axis. Negative factors mirror it
+        --rotate <RX,RY,RZ>   Rotate the knot by these angles in degrees
+                              around the x, then y, then z axis. --scale and
+                              --rotate are applied in the order given, after
+                              --subdivide, --smooth and --decimate
+        --subdivide <LEVELS>  Smooth the knot by subdividing it this many
+                              times. Implies --weld
+        --subdivision <HOW>   Subdivide with catmull-clark (quads) or loop
+                              (triangles). Default catmull-clark
+        --smooth <ITERATIONS> Smooth the knot with Taubin smoothing, which
+                              keeps its volume. Implies --weld
+        --taubin <LAMBDA,MU>  Step sizes for --smooth (default 0.5,-0.53).
+                              A MU of 0 gives Laplacian smoothing
+        --no-pin-bounds       Let --smooth move vertices on the outside of
+                              the grid, which rounds off the flat ends
+        --decimate <FACES>    Simplify the knot down to about this many
+                              triangles. Implies --weld
+    -o, --output <FILE>       Output file (default grid.obj). The format is
+                              chosen from the extension: .obj, .stl, .ply,
+                              .gltf, .glb or .3mf
+        --preview <FILE>      Also save a simplified copy, e.g. for a web
+                              viewer. Implies --weld
+        --preview-faces <N>   Number of triangles in the preview (default
+                              20000)
+        --split               Save each strand to its own file instead,
+                              named after the output with a number added,
+                              e.g. grid_0.obj
+        --ascii               Write the text version of formats that have
+                              one (STL and PLY are binary by default)
+        --cell-size <MM>      Size of one grid cell in millimeters, for
+                              formats with units like 3MF (default 10)
+        --group-by <GROUP>    Split the output into named objects by cell,
+                              by kind of cell, or none (default cell)
+        --color-by <COLORS>   Give each strand, layer or kind of cell its
+                              own material color. OBJ files get a
+                              companion .mtl file
+        --palette <COLORS>    Comma-separated hex colors to use with
+                              --color-by, e.g. #ff0000,#00ff00
+        --normals <NORMALS>   Recompute normals as flat or smooth, or write
+                              none. By default the tiles' normals are kept
+        --crease-angle <DEG>  Edges sharper than this stay sharp with
+                              --normals smooth (default 30)
+        --triangulate <HOW>   Split faces into triangles with fan, shortest
+                              (shorter diagonal of quads) or ear clipping
+        --clip <X,Y,Z,NX,NY,NZ>
+                              Cut the knot with the plane through X,Y,Z,
+                              removing the side NX,NY,NZ points to. Can be
+                              given more than once
+        --cap                 Close the cuts made by --clip. Needs --weld to
+                              close cuts across tile seams";
+
+#[derive(Debug)]
+pub enum CliError {
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        reason: String,
+    },
+    TilesetNotFound(PathBuf),
+    MissingTile(PathBuf),
+    UnsupportedFormat(PathBuf),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CliError::MissingCommand => write!(f, "no command given"),
+            CliError::UnknownCommand(command)
+                => write!(f, "unknown command '{}'", command),
+            CliError::UnknownOption(option)
+                => write!(f, "unknown option '{}'", option),
+            CliError::MissingValue(option)
+                => write!(f, "option '{}' requires a value", option),
+            CliError::InvalidValue { option, value, reason }
+                => write!(f, "invalid value '{}' for '{}': {}",
+                    value, option, reason),
+            CliError::TilesetNotFound(path)
+                => write!(f, "tileset directory '{}' does not exist",
+                    path.display()),
+            CliError::MissingTile(path)
+                => write!(f, "tileset is missing '{}'", path.display()),
+            CliError::UnsupportedFormat(path)
+                => write!(f, "unsupported output format for '{}'",
+                    path.display()),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+#[derive(Debug)]
+pub struct GenerateArgs {
+    pub dimensions: [u32; 3],
+    pub tileset: PathBuf,
+    pub enable_mirrors: bool,
+    pub mirror_chance: f64,
+    pub seed: Option<u64>,
+    pub weld_tolerance: Option<f32>,
+    pub cleanup: bool,
+    pub orient: bool,
+    pub remesh: Option<RemeshOptions>,
+    pub transform: Option<Transform>,
+    pub subdivision_levels: usize,
+    pub subdivision: Subdivision,
+    pub smoothing: SmoothOptions,
+    pub decimate: Option<usize>,
+    pub output: PathBuf,
+    pub format: FileFormat,
+    pub preview: Option<(PathBuf, FileFormat)>,
+    pub preview_faces: usize,
+    pub split: bool,
+    pub cell_size: f32,
+    pub grouping: Option<Grouping>,
+    pub color_by: Option<ColorBy>,
+    pub palette: Vec<Color>,
+    pub normals: Option<Normals>,
+    pub crease_angle: f32,
+    pub triangulation: Option<Triangulation>,
+    pub clip_planes: Vec<(Vertex, Vertex)>,
+    pub cap: bool,
+}
+
+/// How to recompute normals for the output
+#[derive(Copy, Clone, Debug)]
+pub enum Normals {
+    Flat,
+    Smooth,
+    None,
+}
+
+/// What each color of the palette is assigned to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorBy {
+    Strand,
+    Layer,
+    Kind,
+}
+
+impl Default for GenerateArgs {
+    fn default() -> Self {
+        Self {
+            dimensions: [5, 5, 5],
+            tileset: PathBuf::from("sturdy"),
+            enable_mirrors: false,
+            mirror_chance: 200.0 / 256.0,
+            seed: None,
+            weld_tolerance: None,
+            cleanup: false,
+            orient: false,
+            remesh: None,
+            transform: None,
+            subdivision_levels: 0,
+            subdivision: Subdivision::CatmullClark,
+            smoothing: SmoothOptions {
+                iterations: 0,
+                ..SmoothOptions::default()
+            },
+            decimate: None,
+            output: PathBuf::from("grid.obj"),
+            format: FileFormat::Obj,
+            preview: None,
+            preview_faces: 20000,
+            split: false,
+            cell_size: 10.0,
+            grouping: Some(Grouping::Cell),
+            color_by: None,
+            palette: default_palette(),
+            normals: None,
+            crease_angle: DEFAULT_CREASE_ANGLE,
+            triangulation: None,
+            clip_planes: Vec::new(),
+            cap: false,
+        }
+    }
+}
+
+/**
+ * OBJ files to check. If there are none, a grid is generated from the
+ * options instead. --weld and --cleanup also apply to the files.
+ */
+#[derive(Debug)]
+pub struct CheckArgs {
+    pub files: Vec<PathBuf>,
+    pub generate: GenerateArgs,
+    pub intersections: bool,
+}
+
+/// Files or a generated grid to measure, like CheckArgs
+#[derive(Debug)]
+pub struct MeasureArgs {
+    pub files: Vec<PathBuf>,
+    pub generate: GenerateArgs,
+    pub density: f32,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Generate(GenerateArgs),
+    Check(CheckArgs),
+    Measure(MeasureArgs),
+    Help,
+}
+
+/**
+ * Parse the command line arguments (not including the program name)
+ */
+pub fn parse_args<I>(args: I) -> Result<Command, CliError>
+        where I: IntoIterator<Item = String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or(CliError::MissingCommand)?;
+
+    match command.as_str() {
+        "generate" => parse_generate(args).map(Command::Generate),
+        "check" => parse_check(args).map(Command::Check),
+        "measure" => parse_measure(args).map(Command::Measure),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        _ => Err(CliError::UnknownCommand(command))
+    }
+}
+
+fn parse_generate<I>(args: I) -> Result<GenerateArgs, CliError>
+        where I: Iterator<Item = String> {
+    let mut result = parse_generate_options(args)?;
+    result.tileset = resolve_tileset(&result.tileset)?;
+    Ok(result)
+}
+
+/**
+ * The options of generate, without looking for the tileset yet, since
+ * check and measure only need it when they aren't given any files
+ */
+fn parse_generate_options<I>(mut args: I) -> Result<GenerateArgs, CliError>
+        where I: Iterator<Item = String> {
+    let mut result = GenerateArgs::default();
+    let mut ascii = false;
+    let mut preview: Option<PathBuf> = None;
+    let mut remesh_voxels: Option<usize> = None;
+    let mut remesh = RemeshOptions::default();
+
+    while let Some(option) = args.next() {
+        match option.as_str() {
+            "-s" | "--size" => {
+                let value = next_value(&mut args, &option)?;
+                result.dimensions = parse_dimensions(&option, &value)?;
+            },
+            "-t" | "--tileset" => {
+                result.tileset = PathBuf::from(next_value(&mut args, &option)?);
+            },
+            "--mirrors" => {
+                result.enable_mirrors = true;
+            },
+            "--mirror-chance" => {
+                let value = next_value(&mut args, &option)?;
+                result.mirror_chance = parse_probability(&option, &value)?;
+            },
+            "--seed" => {
+                let value = next_value(&mut args, &option)?;
+                let seed = value.parse().map_err(|_| invalid_value(
+                    &option, &value, "expected a non-negative integer"))?;
+                result.seed = Some(seed);
+            },
+            "--weld" => {
+                result.weld_tolerance = result.weld_tolerance
+                    .or(Some(DEFAULT_WELD_TOLERANCE));
+            },
+            "--weld-tolerance" => {
+                let value = next_value(&mut args, &option)?;
+                result.weld_tolerance = Some(parse_positive(&option, &value)?);
+            },
+            "--cleanup" => {
+                result.cleanup = true;
+            },
+            "--orient" => {
+                result.orient = true;
+            },
+            "--remesh" => {
+                let value = next_value(&mut args, &option)?;
+                remesh_voxels = Some(parse_count(&option, &value)?);
+            },
+            "--contouring" => {
+                let value = next_value(&mut args, &option)?;
+                remesh.contouring = match value.as_str() {
+                    "marching-cubes" => Contouring::MarchingCubes,
+                    "dual" => Contouring::DualContouring,
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected marching-cubes or dual"))
+                };
+            },
+            "--smooth-union" => {
+                let value = next_value(&mut args, &option)?;
+                remesh.smooth_union_radius = parse_positive(&option, &value)?;
+            },
+            "--scale" => {
+                let value = next_value(&mut args, &option)?;
+                let scale = match parse_numbers(&option, &value)?[..] {
+                    [s] => Transform::uniform_scale(s),
+                    [sx, sy, sz] => Transform::scale(sx, sy, sz),
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected 1 or 3 numbers"))
+                };
+                if scale.determinant() == 0.0 {
+                    return Err(invalid_value(
+                        &option, &value, "scale factors must not be zero"));
+                }
+                result.transform = Some(then(result.transform, &scale));
+            },
+            "--rotate" => {
+                let value = next_value(&mut args, &option)?;
+                let rotation = match parse_numbers(&option, &value)?[..] {
+                    [x, y, z] => &(&Transform::rotation_z(z.to_radians())
+                        * &Transform::rotation_y(y.to_radians()))
+                        * &Transform::rotation_x(x.to_radians()),
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected 3 angles"))
+                };
+                result.transform = Some(then(result.transform, &rotation));
+            },
+            "--subdivide" => {
+                let value = next_value(&mut args, &option)?;
+                result.subdivision_levels = value.parse().map_err(|_| invalid_value(
+                    &option, &value, "expected a non-negative integer"))?;
+            },
+            "--subdivision" => {
+                let value = next_value(&mut args, &option)?;
+                result.subdivision = match value.as_str() {
+                    "catmull-clark" => Subdivision::CatmullClark,
+                    "loop" => Subdivision::Loop,
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected catmull-clark or loop"))
+                };
+            },
+            "--smooth" => {
+                let value = next_value(&mut args, &option)?;
+                result.smoothing.iterations = value.parse().map_err(|_| invalid_value(
+                    &option, &value, "expected a non-negative integer"))?;
+            },
+            "--taubin" => {
+                let value = next_value(&mut args, &option)?;
+                match parse_numbers(&option, &value)?[..] {
+                    [lambda, mu] => {
+                        result.smoothing.lambda = lambda;
+                        result.smoothing.mu = mu;
+                    },
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected LAMBDA,MU"))
+                }
+            },
+            "--no-pin-bounds" => {
+                result.smoothing.pin_bounds = false;
+            },
+            "--decimate" => {
+                let value = next_value(&mut args, &option)?;
+                result.decimate = Some(parse_count(&option, &value)?);
+            },
+            "--preview" => {
+                preview = Some(PathBuf::from(next_value(&mut args, &option)?));
+            },
+            "--preview-faces" => {
+                let value = next_value(&mut args, &option)?;
+                result.preview_faces = parse_count(&option, &value)?;
+            },
+            "--split" => {
+                result.split = true;
+            },
+            "-o" | "--output" => {
+                let value = next_value(&mut args, &option)?;
+                result.output = PathBuf::from(value);
+            },
+            "--ascii" => {
+                ascii = true;
+            },
+            "--cell-size" => {
+                let value = next_value(&mut args, &option)?;
+                result.cell_size = parse_positive(&option, &value)?;
+            },
+            "--group-by" => {
+                let value = next_value(&mut args, &option)?;
+                result.grouping = match value.as_str() {
+                    "cell" => Some(Grouping::Cell),
+                    "kind" => Some(Grouping::Kind),
+                    "none" => None,
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected cell, kind or none"))
+                };
+            },
+            "--color-by" => {
+                let value = next_value(&mut args, &option)?;
+                result.color_by = match value.as_str() {
+                    "strand" => Some(ColorBy::Strand),
+                    "layer" => Some(ColorBy::Layer),
+                    "kind" => Some(ColorBy::Kind),
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected strand, layer or kind"))
+                };
+            },
+            "--palette" => {
+                let value = next_value(&mut args, &option)?;
+                result.palette = parse_palette(&option, &value)?;
+            },
+            "--normals" => {
+                let value = next_value(&mut args, &option)?;
+                result.normals = match value.as_str() {
+                    "flat" => Some(Normals::Flat),
+                    "smooth" => Some(Normals::Smooth),
+                    "none" => Some(Normals::None),
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected flat, smooth or none"))
+                };
+            },
+            "--crease-angle" => {
+                let value = next_value(&mut args, &option)?;
+                result.crease_angle = parse_positive(&option, &value)?;
+            },
+            "--triangulate" => {
+                let value = next_value(&mut args, &option)?;
+                result.triangulation = match value.as_str() {
+                    "fan" => Some(Triangulation::Fan),
+                    "shortest" => Some(Triangulation::ShortestDiagonal),
+                    "ear" => Some(Triangulation::EarClipping),
+                    _ => return Err(invalid_value(
+                        &option, &value, "expected fan, shortest or ear"))
+                };
+            },
+            "--clip" => {
+                let value = next_value(&mut args, &option)?;
+                result.clip_planes.push(parse_plane(&option, &value)?);
+            },
+            "--cap" => {
+                result.cap = true;
+            },
+            _ => return Err(CliError::UnknownOption(option))
+        }
+    }
+
+    // Subdividing, smoothing or decimating an unwelded grid would pull the
+    // tiles apart
+    let needs_weld = result.subdivision_levels > 0
+        || result.smoothing.iterations > 0
+        || result.decimate.is_some()
+        || preview.is_some();
+    if needs_weld && result.weld_tolerance.is_none() {
+        result.weld_tolerance = Some(DEFAULT_WELD_TOLERANCE);
+    }
+
+    result.remesh = remesh_voxels.map(|voxels| RemeshOptions {
+        voxel_size: 1.0 / voxels as f32,
+        ..remesh
+    });
+
+    result.format = FileFormat::from_path(&result.output)
+        .ok_or_else(|| CliError::UnsupportedFormat(result.output.clone()))?;
+    if ascii {
+        result.format = result.format.ascii();
+    }
+    if let Some(path) = preview {
+        let mut format = FileFormat::from_path(&path)
+            .ok_or_else(|| CliError::UnsupportedFormat(path.clone()))?;
+        if ascii {
+            format = format.ascii();
+        }
+        result.preview = Some((path, format));
+    }
+
+    Ok(result)
+}
+
+fn parse_check<I>(args: I) -> Result<CheckArgs, CliError>
+        where I: Iterator<Item = String> {
+    let mut args = args.peekable();
+    let files = parse_files(&mut args);
+
+    let mut intersections = false;
+    let generate_args: Vec<String> = args.filter(|option| {
+        let ours = option == "--intersections";
+        intersections |= ours;
+        !ours
+    }).collect();
+
+    Ok(CheckArgs {
+        generate: parse_generate_for(&files, generate_args)?,
+        files,
+        intersections,
+    })
+}
+
+fn parse_measure<I>(args: I) -> Result<MeasureArgs, CliError>
+        where I: Iterator<Item = String> {
+    let mut args = args.peekable();
+    let files = parse_files(&mut args);
+
+    // Pick out the options of our own and leave the rest to generate
+    let mut density = 1.24;
+    let mut generate_args: Vec<String> = Vec::new();
+    while let Some(option) = args.next() {
+        if option == "--density" {
+            let value = next_value(&mut args, &option)?;
+            density = parse_positive(&option, &value)?;
+        } else {
+            generate_args.push(option);
+        }
+    }
+
+    Ok(MeasureArgs {
+        generate: parse_generate_for(&files, generate_args)?,
+        files,
+        density,
+    })
+}
+
+/// The generate options for check or measure, which only need the tileset
+/// if there are no files
+fn parse_generate_for(files: &[PathBuf], args: Vec<String>)
+        -> Result<GenerateArgs, CliError> {
+    if files.is_empty() {
+        parse_generate(args.into_iter())
+    } else {
+        parse_generate_options(args.into_iter())
+    }
+}
+
+/// File names given before any options
+fn parse_files<I>(args: &mut Peekable<I>) -> Vec<PathBuf>
+        where I: Iterator<Item = String> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    while let Some(file) = args.next_if(|arg| !arg.starts_with('-')) {
+        files.push(PathBuf::from(file));
+    }
+
+    files
+}
+
+fn next_value<I>(args: &mut I, option: &str) -> Result<String, CliError>
+        where I: Iterator<Item = String> {
+    args.next().ok_or_else(|| CliError::MissingValue(option.to_string()))
+}
+
+fn invalid_value(option: &str, value: &str, reason: &str) -> CliError {
+    CliError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn parse_dimensions(option: &str, value: &str) -> Result<[u32; 3], CliError> {
+    let parts: Vec<&str> = value.split('x').collect();
+    let mut sizes: Vec<u32> = Vec::new();
+    for part in parts.iter() {
+        let size: u32 = part.trim().parse().map_err(|_| invalid_value(
+            option, value, "expected dimensions like 5x5x5"))?;
+        if size == 0 {
+            return Err(invalid_value(
+                option, value, "dimensions must be at least 1"));
+        }
+        sizes.push(size);
+    }
+
+    match sizes[..] {
+        [n] => Ok([n, n, n]),
+        [n, m, p] => Ok([n, m, p]),
+        _ => Err(invalid_value(
+            option, value, "expected either 1 or 3 dimensions"))
+    }
+}
+
+fn parse_count(option: &str, value: &str) -> Result<usize, CliError> {
+    let count: usize = value.parse().map_err(|_| invalid_value(
+        option, value, "expected a positive integer"))?;
+    if count == 0 {
+        return Err(invalid_value(option, value, "must be at least 1"));
+    }
+
+    Ok(count)
+}
+
+fn parse_probability(option: &str, value: &str) -> Result<f64, CliError> {
+    let probability: f64 = value.parse().map_err(|_| invalid_value(
+        option, value, "expected a number"))?;
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(invalid_value(
+            option, value, "probability must be between 0 and 1"));
+    }
+
+    Ok(probability)
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<f32, CliError> {
+    let number: f32 = value.parse().map_err(|_| invalid_value(
+        option, value, "expected a number"))?;
+    if !(number > 0.0 && number.is_finite()) {
+        return Err(invalid_value(option, value, "must be greater than 0"));
+    }
+
+    Ok(number)
+}
+
+/// Apply next after the transform so far, if any
+fn then(transform: Option<Transform>, next: &Transform) -> Transform {
+    match transform {
+        Some(transform) => next * &transform,
+        None => *next
+    }
+}
+
+fn parse_numbers(option: &str, value: &str) -> Result<Vec<f32>, CliError> {
+    value.split(',')
+        .map(|number| number.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid_value(
+            option, value, "expected comma-separated numbers"))
+}
+
+fn parse_plane(option: &str, value: &str)
+        -> Result<(Vertex, Vertex), CliError> {
+    match parse_numbers(option, value)?[..] {
+        [x, y, z, nx, ny, nz] => {
+            let normal = Vertex([nx, ny, nz]);
+            if normal.length() == 0.0 {
+                return Err(invalid_value(
+                    option, value, "the normal must not be zero"));
+            }
+            Ok((Vertex([x, y, z]), normal))
+        },
+        _ => Err(invalid_value(
+            option, value, "expected a point and a normal like 0,0,0,0,0,-1"))
+    }
+}
+
+fn parse_palette(option: &str, value: &str) -> Result<Vec<Color>, CliError> {
+    value.split(',').map(|hex| {
+        parse_hex_color(hex.trim()).ok_or_else(|| invalid_value(
+            option, value, "expected hex colors like #ff8800"))
+    }).collect()
+}
+
+/**
+ * A tileset can either be given as a directory path or as the name of one of
+ * the directories under data/. Either way, it must contain all the tile
+ * OBJ files.
+ */
+fn resolve_tileset(tileset: &Path) -> Result<PathBuf, CliError> {
+    let dir = if tileset.is_dir() {
+        tileset.to_path_buf()
+    } else {
+        Path::new("data").join(tileset)
+    };
+
+    if !dir.is_dir() {
+        return Err(CliError::TilesetNotFound(dir));
+    }
+
+    for name in TILE_NAMES.iter() {
+        let path = format_path(&dir, name);
+        if !path.is_file() {
+            return Err(CliError::MissingTile(path));
+        }
+    }
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn a_single_size_makes_a_cube() {
+        assert_eq!(parse_dimensions("--size", "3").unwrap(), [3, 3, 3]);
+    }
+
+    #[test]
+    fn sizes_can_be_given_for_each_axis() {
+        assert_eq!(parse_dimensions("--size", "1x4x2").unwrap(), [1, 4, 2]);
+        assert_eq!(parse_dimensions("--size", " 2 x 3 x 4").unwrap(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        for value in ["", "x", "0", "3x0x3", "-1", "2x3", "2x3x4x5", "axbxc"].iter() {
+            match parse_dimensions("--size", value) {
+                Err(CliError::InvalidValue { option, .. })
+                    => assert_eq!(option, "--size"),
+                result => panic!("'{}' gave {:?}", value, result),
+            }
+        }
+    }
+
+    #[test]
+    fn options_are_read_into_the_generate_args() {
+        let args = [
+            "generate", "--size", "2x3x4", "--mirrors", "--mirror-chance", "0.5",
+            "--seed", "7", "-o", "knot.obj"
+        ];
+        match parse(&args) {
+            Ok(Command::Generate(args)) => {
+                assert_eq!(args.dimensions, [2, 3, 4]);
+                assert!(args.enable_mirrors);
+                assert_eq!(args.mirror_chance, 0.5);
+                assert_eq!(args.seed, Some(7));
+                assert_eq!(args.output, PathBuf::from("knot.obj"));
+                assert_eq!(args.tileset, Path::new("data").join("sturdy"));
+            },
+            result => panic!("expected generate, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn unknown_commands_and_options_are_rejected() {
+        assert!(matches!(parse(&[]), Err(CliError::MissingCommand)));
+        assert!(matches!(parse(&["make"]),
+            Err(CliError::UnknownCommand(command)) if command == "make"));
+        assert!(matches!(parse(&["generate", "--size", "3", "--colour"]),
+            Err(CliError::UnknownOption(option)) if option == "--colour"));
+    }
+
+    #[test]
+    fn options_without_values_are_rejected() {
+        for option in ["--size", "-t", "--mirror-chance", "--seed", "-o"].iter() {
+            match parse(&["generate", option]) {
+                Err(CliError::MissingValue(missing)) => assert_eq!(missing, *option),
+                result => panic!("'{}' gave {:?}", option, result),
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        assert!(parse(&["generate", "--mirror-chance", "1.5"]).is_err());
+        assert!(parse(&["generate", "--seed", "-4"]).is_err());
+        assert!(matches!(parse(&["generate", "--tileset", "no_such_tileset"]),
+            Err(CliError::TilesetNotFound(_))));
+    }
+
+    fn weld_tolerance(args: &[&str]) -> Option<f32> {
+        match parse(args) {
+            Ok(Command::Generate(args)) => args.weld_tolerance,
+            result => panic!("expected generate, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn operations_on_shared_vertices_imply_weld() {
+        assert_eq!(weld_tolerance(&["generate"]), None);
+        assert_eq!(weld_tolerance(&["generate", "--split"]), None);
+        assert_eq!(weld_tolerance(&["generate", "--subdivide", "1"]),
+            Some(DEFAULT_WELD_TOLERANCE));
+        assert_eq!(weld_tolerance(&["generate", "--smooth", "4"]),
+            Some(DEFAULT_WELD_TOLERANCE));
+        assert_eq!(weld_tolerance(&["generate", "--decimate", "500"]),
+            Some(DEFAULT_WELD_TOLERANCE));
+        assert_eq!(weld_tolerance(&["generate", "--preview", "preview.glb"]),
+            Some(DEFAULT_WELD_TOLERANCE));
+        let explicit = ["generate", "--weld-tolerance", "0.01", "--subdivide", "1"];
+        assert_eq!(weld_tolerance(&explicit), Some(0.01));
+    }
+}
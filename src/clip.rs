@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::{Mesh, FaceAttributes, remove_repeated_corners};
+use crate::triangulate::clip_ears;
+
+/// Vertices closer to the plane than this count as being on it
+const ON_PLANE: f32 = 1e-5;
+
+impl Mesh {
+    /**
+     * Cut the mesh with the plane through point with the given normal.
+     * Like simple_clip(), the normal points towards the side that is
+     * removed. Faces that straddle the plane are split at it, with uvs and
+     * normals interpolated along the cut edges. The cut is left open, see
+     * clip_plane_capped() to close it.
+     */
+    pub fn clip_plane(&self, point: Vertex, normal: Vertex) -> Self {
+        Clipper::new(self, point, normal).clip().0
+    }
+
+    /**
+     * Like clip_plane(), but also close each loop of the cut with triangles
+     * facing along the normal, e.g. to make a flat base. A cap gets the
+     * attributes (object, material, cell and so on) of a face it borders,
+     * so it stays part of the strand it closes. Cut edges are
+     * followed by vertex index, so weld the mesh first or the caps will not
+     * find their way around tile seams. Loops that do not close (where the
+     * mesh was already open) are left uncapped, and holes in a cross
+     * section get a cap of their own rather than being cut out of the
+     * surrounding one.
+     */
+    pub fn clip_plane_capped(&self, point: Vertex, normal: Vertex) -> Self {
+        let (mut result, on_plane) = Clipper::new(self, point, normal).clip();
+        result.cap(&on_plane, &normal.normalize());
+        result
+    }
+
+    /**
+     * Fill the boundary loops that lie on the cutting plane. The caps are
+     * wound opposite to the faces they border, so they face the same way
+     * as the rest of the surface.
+     */
+    fn cap(&mut self, on_plane: &[bool], normal: &Vertex) {
+        let mut edge_counts: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut edges: Vec<(usize, usize, usize)> = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let indices = face.indices();
+            for (i, a) in indices.iter().enumerate() {
+                let b = indices[(i + 1) % indices.len()];
+                *edge_counts.entry((*a, b)).or_insert(0) += 1;
+                edges.push((*a, b, f));
+            }
+        }
+
+        // Boundary edges on the plane, reversed for the caps, and the faces
+        // they border
+        let mut cap_edges: Vec<(usize, usize, usize)> = Vec::new();
+        let mut next: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (a, b, f) in edges {
+            if on_plane[a] && on_plane[b] && !edge_counts.contains_key(&(b, a)) {
+                cap_edges.push((b, a, f));
+                next.entry(b).or_default().push(a);
+            }
+        }
+
+        let mut loops: Vec<(Vec<usize>, usize)> = Vec::new();
+        for (start, second, neighbor) in cap_edges {
+            let unused = next.get_mut(&start)
+                .and_then(|ends| ends.iter().position(|v| *v == second)
+                    .map(|i| ends.remove(i)));
+            if unused.is_none() {
+                continue;
+            }
+
+            let mut cap_loop = vec![start];
+            let mut current = second;
+            let mut closed = false;
+            while let Some(following) = next.get_mut(&current).and_then(|ends| ends.pop()) {
+                cap_loop.push(current);
+                if following == start {
+                    closed = true;
+                    break;
+                }
+                current = following;
+            }
+
+            if closed && cap_loop.len() >= 3 {
+                loops.push((cap_loop, neighbor));
+            }
+        }
+
+        if loops.is_empty() {
+            return;
+        }
+
+        // Give the caps a normal if the rest of the mesh has them
+        let cap_normal = if self.has_normals() {
+            self.normals.push(*normal);
+            Some(self.normals.len() - 1)
+        } else {
+            None
+        };
+
+        // A cross section of a knot is usually concave, so the caps are ear
+        // clipped rather than left as polygons for the exporters to fan
+        for (cap_loop, neighbor) in loops {
+            let attributes = FaceAttributes {
+                uvs: None,
+                normals: None,
+                ..self.face_attributes[neighbor].clone()
+            };
+            for [a, b, c] in clip_ears(&self.vertices, &cap_loop, normal) {
+                let face = Face::Triangle([cap_loop[a], cap_loop[b], cap_loop[c]]);
+                self.push_face(face.clone(), FaceAttributes {
+                    normals: cap_normal.map(|n| face.map(|_| n)),
+                    ..attributes.clone()
+                });
+            }
+        }
+    }
+}
+
+/**
+ * State for clipping one mesh. New vertices, uvs and normals made where the
+ * plane crosses an edge are cached by edge so neighboring faces share them.
+ */
+struct Clipper<'a> {
+    mesh: &'a Mesh,
+    distances: Vec<f32>,
+    result: Mesh,
+    on_plane: Vec<bool>,
+    new_vertex_indices: Vec<Option<usize>>,
+    edge_vertices: HashMap<(usize, usize), usize>,
+    edge_uvs: HashMap<[usize; 4], usize>,
+    edge_normals: HashMap<[usize; 4], usize>,
+}
+
+/// One corner of a face: its vertex, uv and normal indices
+type Corner = (usize, Option<usize>, Option<usize>);
+
+impl<'a> Clipper<'a> {
+    fn new(mesh: &'a Mesh, point: Vertex, normal: Vertex) -> Self {
+        let normal = normal.normalize();
+        let distances = mesh.vertices.iter().map(|vertex| {
+            let distance = vertex.sub(&point).dot(&normal);
+            if distance.abs() < ON_PLANE { 0.0 } else { distance }
+        }).collect();
+
+        Self {
+            mesh,
+            distances,
+            result: Mesh {
+                vertices: Vec::new(),
+                faces: Vec::new(),
+                face_attributes: Vec::new(),
+                ..mesh.clone()
+            },
+            on_plane: Vec::new(),
+            new_vertex_indices: vec![None; mesh.vertices.len()],
+            edge_vertices: HashMap::new(),
+            edge_uvs: HashMap::new(),
+            edge_normals: HashMap::new(),
+        }
+    }
+
+    /// Clip every face, returning the result and which vertices are on the plane
+    fn clip(mut self) -> (Mesh, Vec<bool>) {
+        let mesh = self.mesh;
+        let faces = mesh.faces.iter().zip(mesh.face_attributes.iter());
+        for (face, attributes) in faces {
+            self.clip_face(face, attributes);
+        }
+
+        (self.result, self.on_plane)
+    }
+
+    /**
+     * Sutherland-Hodgman clipping of one face: walk around it keeping the
+     * corners on the inside, and add a corner wherever an edge crosses the
+     * plane.
+     */
+    fn clip_face(&mut self, face: &Face, attributes: &FaceAttributes) {
+        let indices = face.indices();
+        let corner = |i: usize| -> Corner {
+            (
+                indices[i],
+                attributes.uvs.as_ref().map(|uvs| uvs.indices()[i]),
+                attributes.normals.as_ref().map(|normals| normals.indices()[i])
+            )
+        };
+
+        let mut corners: Vec<Corner> = Vec::new();
+        for i in 0..indices.len() {
+            let current = corner(i);
+            let following = corner((i + 1) % indices.len());
+            let current_distance = self.distances[current.0];
+            let following_distance = self.distances[following.0];
+
+            if current_distance <= 0.0 {
+                corners.push((
+                    self.keep_vertex(current.0), current.1, current.2));
+            }
+            if current_distance * following_distance < 0.0 {
+                corners.push(self.split_edge(current, following));
+            }
+        }
+
+        if corners.len() < 3 {
+            return;
+        }
+
+        let new_face = Face::from_indices(
+            corners.iter().map(|(vertex, _, _)| *vertex).collect());
+        let pick = |index: fn(&Corner) -> Option<usize>| {
+            corners.iter().map(index).collect::<Option<Vec<usize>>>()
+                .map(Face::from_indices)
+        };
+        let new_attributes = FaceAttributes {
+            uvs: pick(|(_, uv, _)| *uv),
+            normals: pick(|(_, _, normal)| *normal),
+            ..attributes.clone()
+        };
+
+        if let Some((face, attributes))
+                = remove_repeated_corners(&new_face, &new_attributes) {
+            self.result.push_face(face, attributes);
+        }
+    }
+
+    fn keep_vertex(&mut self, vertex: usize) -> usize {
+        if let Some(index) = self.new_vertex_indices[vertex] {
+            return index;
+        }
+
+        let index = self.push_vertex(self.mesh.vertices[vertex]);
+        self.on_plane[index] = self.distances[vertex] == 0.0;
+        self.new_vertex_indices[vertex] = Some(index);
+        index
+    }
+
+    fn push_vertex(&mut self, vertex: Vertex) -> usize {
+        self.result.vertices.push(vertex);
+        self.on_plane.push(true);
+        self.result.vertices.len() - 1
+    }
+
+    /// The corner where the plane crosses the edge between two corners
+    fn split_edge(&mut self, a: Corner, b: Corner) -> Corner {
+        // Always interpolate from the lower vertex index so the shared
+        // edge of two faces gives exactly the same point
+        let (a, b) = if a.0 <= b.0 { (a, b) } else { (b, a) };
+        let distance_a = self.distances[a.0];
+        let t = distance_a / (distance_a - self.distances[b.0]);
+        let lerp = |start: &Vertex, end: &Vertex| start.add(&end.sub(start).scale(t));
+
+        let vertex = match self.edge_vertices.get(&(a.0, b.0)) {
+            Some(vertex) => *vertex,
+            None => {
+                let position = lerp(&self.mesh.vertices[a.0], &self.mesh.vertices[b.0]);
+                let vertex = self.push_vertex(position);
+                self.edge_vertices.insert((a.0, b.0), vertex);
+                vertex
+            }
+        };
+
+        let uv = match (a.1, b.1) {
+            (Some(uv_a), Some(uv_b)) => {
+                let key = [a.0, b.0, uv_a, uv_b];
+                let uvs = &mut self.result.uvs;
+                Some(*self.edge_uvs.entry(key).or_insert_with(|| {
+                    let [u_a, v_a] = uvs[uv_a];
+                    let [u_b, v_b] = uvs[uv_b];
+                    uvs.push([u_a + (u_b - u_a) * t, v_a + (v_b - v_a) * t]);
+                    uvs.len() - 1
+                }))
+            },
+            _ => None
+        };
+
+        let normal = match (a.2, b.2) {
+            (Some(normal_a), Some(normal_b)) => {
+                let key = [a.0, b.0, normal_a, normal_b];
+                let normals = &mut self.result.normals;
+                Some(*self.edge_normals.entry(key).or_insert_with(|| {
+                    let normal = lerp(&normals[normal_a], &normals[normal_b]);
+                    normals.push(normal.normalize());
+                    normals.len() - 1
+                }))
+            },
+            _ => None
+        };
+
+        (vertex, uv, normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::CellID;
+    use crate::triangulate::Triangulation;
+    use crate::test_meshes::{cube, is_closed, fan_volume};
+
+    fn middle() -> Vertex {
+        Vertex([0.5, 0.5, 0.5])
+    }
+
+    fn up() -> Vertex {
+        Vertex([0.0, 0.0, 1.0])
+    }
+
+    /// An L shaped prism from z = 0 to 1, with triangulated ends
+    fn l_prism() -> Mesh {
+        let outline = [
+            [2.0, 1.0], [1.0, 1.0], [1.0, 2.0], [0.0, 2.0], [0.0, 0.0], [2.0, 0.0]
+        ];
+        let n = outline.len();
+        let vertices = [0.0, 1.0].iter()
+            .flat_map(|z| outline.iter().map(move |[x, y]| Vertex([*x, *y, *z])))
+            .collect();
+        let mut faces = vec![
+            Face::from_indices((0..n).rev().collect()),
+            Face::from_indices((n..2 * n).collect()),
+        ];
+        for i in 0..n {
+            let j = (i + 1) % n;
+            faces.push(Face::Quad([i, j, j + n, i + n]));
+        }
+
+        Mesh::from_geometry(vertices, faces).triangulate(Triangulation::EarClipping)
+    }
+
+    #[test]
+    fn clipping_removes_the_side_the_normal_points_to() {
+        let clipped = cube().clip_plane(middle(), up());
+        let heights = clipped.vertices().iter().map(|Vertex([_, _, z])| *z);
+        assert_eq!(heights.fold(0.0, f32::max), 0.5);
+
+        // The cut is left open
+        assert_eq!(clipped.faces().len(), 5);
+        assert!(!is_closed(&clipped));
+    }
+
+    #[test]
+    fn capped_cube_stays_watertight() {
+        let normals = [
+            up(),
+            Vertex([1.0, 1.0, 1.0]),
+            Vertex([-1.0, 2.0, 0.5]),
+        ];
+        for normal in normals.iter() {
+            let clipped = cube().clip_plane_capped(middle(), *normal);
+            assert!(is_closed(&clipped), "cut along {:?}", normal);
+            let volume = fan_volume(&clipped);
+            assert!((volume - 0.5).abs() < 1e-5, "cut along {:?}", normal);
+        }
+    }
+
+    #[test]
+    fn concave_cuts_are_capped_with_triangles() {
+        let clipped = l_prism().clip_plane_capped(middle(), up());
+        assert!(is_closed(&clipped));
+        assert!((fan_volume(&clipped) - 1.5).abs() < 1e-5);
+
+        let caps: Vec<usize> = (0..clipped.faces().len())
+            .filter(|f| clipped.faces()[*f].indices().iter()
+                .all(|v| clipped.vertices()[*v].0[2] == 0.5))
+            .collect();
+        // The split side quads add a corner on each diagonal, making 12
+        assert_eq!(caps.len(), 10);
+        for f in caps {
+            assert_eq!(clipped.faces()[f].len(), 3);
+            assert!(clipped.face_normal(f).dot(&up()) > 0.99);
+        }
+    }
+
+    #[test]
+    fn caps_belong_to_the_faces_they_close() {
+        let mut mesh = cube();
+        for attributes in mesh.face_attributes.iter_mut() {
+            attributes.object = Some(0);
+            attributes.cell = Some(CellID(1, 2, 3));
+        }
+
+        let clipped = mesh.clip_plane_capped(middle(), up());
+        assert_eq!(clipped.faces().len(), 7);
+        for attributes in clipped.face_attributes() {
+            assert_eq!(attributes.object, Some(0));
+            assert_eq!(attributes.cell, Some(CellID(1, 2, 3)));
+        }
+    }
+
+    #[test]
+    fn planes_outside_the_mesh_keep_all_or_nothing() {
+        let above = cube().clip_plane_capped(Vertex([0.0, 0.0, 2.0]), up());
+        assert_eq!(above.faces().len(), 6);
+        assert!(is_closed(&above));
+
+        let below = cube().clip_plane_capped(Vertex([0.0, 0.0, -1.0]), up());
+        assert!(below.faces().is_empty());
+    }
+}
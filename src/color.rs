@@ -0,0 +1,111 @@
+/// Colors are stored as linear RGB in [0, 1]
+pub type Color = [f32; 3];
+
+/// A palette of easily distinguished colors, as sRGB hex strings
+pub const DEFAULT_PALETTE: [&str; 8] = [
+    "#e6194b",
+    "#3cb44b",
+    "#ffe119",
+    "#4363d8",
+    "#f58231",
+    "#911eb4",
+    "#42d4f4",
+    "#f032e6",
+];
+
+pub fn default_palette() -> Vec<Color> {
+    DEFAULT_PALETTE.iter()
+        .filter_map(|hex| parse_hex_color(hex))
+        .collect()
+}
+
+/**
+ * Parse an sRGB hex color like #ff8800 (the # is optional) into linear RGB
+ */
+pub fn parse_hex_color(hex: &str) -> Option<Color> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 || !digits.is_ascii() {
+        return None;
+    }
+
+    let mut color = [0.0; 3];
+    for (i, channel) in color.iter_mut().enumerate() {
+        let value = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+        *channel = srgb_to_linear(value as f32 / 255.0);
+    }
+
+    Some(color)
+}
+
+/// Format a linear RGB color as an sRGB hex string like #RRGGBB
+pub fn hex_color(color: &Color) -> String {
+    let channels: Vec<String> = srgb_bytes(color).iter()
+        .map(|c| format!("{:02X}", c))
+        .collect();
+
+    format!("#{}", channels.join(""))
+}
+
+/// Convert a linear RGB color to 8-bit sRGB
+pub fn srgb_bytes(color: &Color) -> [u8; 3] {
+    color.map(|c| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8)
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors_round_trip() {
+        for hex in ["#000000", "#FFFFFF", "#FF8000", "#E6194B"].iter() {
+            assert_eq!(hex_color(&parse_hex_color(hex).unwrap()), *hex);
+        }
+        assert_eq!(parse_hex_color("ff8000"), parse_hex_color("#FF8000"));
+    }
+
+    #[test]
+    fn invalid_hex_colors_are_rejected() {
+        for hex in ["", "#", "#ff80", "#ff80000", "#gg8000", "#ff80é"].iter() {
+            assert_eq!(parse_hex_color(hex), None, "{}", hex);
+        }
+    }
+
+    #[test]
+    fn hex_colors_are_srgb() {
+        let [r, g, b] = parse_hex_color("#ff8000").unwrap();
+        assert!((r - 1.0).abs() < 1e-6);
+        assert!((g - 0.215_861).abs() < 1e-5);
+        assert_eq!(b, 0.0);
+        assert_eq!(srgb_bytes(&[1.0, g, 0.0]), [255, 128, 0]);
+    }
+
+    #[test]
+    fn srgb_conversions_are_inverses() {
+        for i in 0..=100 {
+            let value = i as f32 / 100.0;
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn out_of_range_colors_are_clamped() {
+        assert_eq!(srgb_bytes(&[-0.5, 2.0, 0.0]), [0, 255, 0]);
+    }
+}
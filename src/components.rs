@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use crate::primitives::Face;
+use crate::mesh::Mesh;
+
+impl Mesh {
+    /**
+     * Split the mesh into pieces that don't touch, e.g. the separate
+     * strands of a knot. These are the same pieces as parts_by_strand, so
+     * tiles that only share vertex positions still come out together.
+     * Components are ordered by their first face.
+     */
+    pub fn connected_components(&self) -> Vec<Self> {
+        self.parts_by_strand().faces_per_part().iter()
+            .map(|faces| self.extract_faces(faces))
+            .collect()
+    }
+
+    /**
+     * A mesh with only the given faces, keeping their attributes. Vertices,
+     * uvs and normals that the faces don't use are left out. The name
+     * tables are kept as they are.
+     */
+    pub fn extract_faces(&self, face_indices: &[usize]) -> Self {
+        let mut vertices = Remap::default();
+        let mut uvs = Remap::default();
+        let mut normals = Remap::default();
+        let mut result = Self {
+            vertices: Vec::new(),
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            ..self.clone()
+        };
+
+        for f in face_indices.iter() {
+            let attributes = &self.face_attributes[*f];
+            let face = vertices.face(&self.faces[*f]);
+            let mut new_attributes = attributes.clone();
+            new_attributes.uvs = attributes.uvs.as_ref()
+                .map(|corners| uvs.face(corners));
+            new_attributes.normals = attributes.normals.as_ref()
+                .map(|corners| normals.face(corners));
+            result.push_face(face, new_attributes);
+        }
+
+        result.vertices = vertices.pick(&self.vertices);
+        result.uvs = uvs.pick(&self.uvs);
+        result.normals = normals.pick(&self.normals);
+        result
+    }
+}
+
+/// New indices for the old indices that are used, in order of first use
+#[derive(Default)]
+struct Remap {
+    new_indices: HashMap<usize, usize>,
+    used: Vec<usize>,
+}
+
+impl Remap {
+    fn face(&mut self, face: &Face) -> Face {
+        let mut indices = Vec::with_capacity(face.len());
+        for old in face.indices().iter() {
+            let used = &mut self.used;
+            indices.push(*self.new_indices.entry(*old).or_insert_with(|| {
+                used.push(*old);
+                used.len() - 1
+            }));
+        }
+
+        Face::from_indices(indices)
+    }
+
+    /// The used values, in their new order
+    fn pick<T: Copy>(&self, values: &[T]) -> Vec<T> {
+        self.used.iter().map(|old| values[*old]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::Vertex;
+    use crate::test_meshes::{cube, unwelded_cube};
+
+    #[test]
+    fn separate_cubes_are_separate_components() {
+        let mut mesh = cube();
+        mesh.add_geometry(&cube().translate(&[3.0, 0.0, 0.0]));
+
+        let components = mesh.connected_components();
+        assert_eq!(components.len(), 2);
+        for (component, offset) in components.iter().zip([0.0, 3.0].iter()) {
+            assert_eq!(component.vertices().len(), 8);
+            assert_eq!(component.faces().len(), 6);
+            assert!(component.analyze().is_clean());
+            let (Vertex(min), _) = component.bounding_box().unwrap();
+            assert_eq!(min[0], *offset);
+        }
+    }
+
+    #[test]
+    fn unwelded_faces_are_one_component() {
+        assert_eq!(unwelded_cube().connected_components().len(), 1);
+        assert_eq!(unwelded_cube().weld(1e-5).connected_components().len(), 1);
+    }
+
+    #[test]
+    fn components_match_strands() {
+        let mut mesh = unwelded_cube();
+        mesh.add_geometry(&cube().translate(&[3.0, 0.0, 0.0]));
+
+        let strands = mesh.parts_by_strand();
+        let components = mesh.connected_components();
+        assert_eq!(components.len(), strands.len());
+        for (component, faces) in components.iter().zip(strands.faces_per_part()) {
+            assert_eq!(component.faces().len(), faces.len());
+        }
+    }
+
+    #[test]
+    fn extracted_faces_only_keep_what_they_use() {
+        let extracted = cube().with_flat_normals().extract_faces(&[1]);
+        assert_eq!(extracted.faces().len(), 1);
+        assert_eq!(extracted.vertices().len(), 4);
+        assert!(extracted.vertices().iter().all(|Vertex([x, _, _])| *x == 1.0));
+        assert_eq!(extracted.normals(), &[Vertex([1.0, 0.0, 0.0])]);
+        assert_eq!(extracted.face_attributes()[0].normals, Some(Face::Quad([0; 4])));
+    }
+}
@@ -93,9 +93,9 @@ impl Debug for CubeRotation {
     }
 }
 
-impl<'a, 'b> Mul<&'b CubeRotation> for &'a CubeRotation {
+impl Mul<&CubeRotation> for &CubeRotation {
     type Output = CubeRotation;
-    fn mul(self, other: &'b CubeRotation) -> CubeRotation {
+    fn mul(self, other: &CubeRotation) -> CubeRotation {
         let mut axes: [usize; 3] = [0; 3];
         let mut orientations: [i8; 3] = [1; 3];
 
@@ -114,15 +114,15 @@ impl<'a, 'b> Mul<&'b CubeRotation> for &'a CubeRotation {
     }
 }
 
-impl<'a, 'b> Mul<&'b Vertex> for &'a CubeRotation {
+impl Mul<&Vertex> for &CubeRotation {
     type Output = Vertex;
-    fn mul(self, other: &'b Vertex) -> Vertex {
+    fn mul(self, other: &Vertex) -> Vertex {
         let Vertex(components) = other;
         let mut new_components: [f32; 3] = [0.0; 3];
-        for i in 0..3 {
+        for (i, component) in new_components.iter_mut().enumerate() {
             let axis = self.axes[i];
             let orientation = self.orientations[i];
-            new_components[i] = (orientation as f32) * components[axis];
+            *component = (orientation as f32) * components[axis];
         }
 
         Vertex(new_components)
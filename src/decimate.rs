@@ -0,0 +1,528 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::{Mesh, FaceAttributes};
+use crate::triangulate::Triangulation;
+use crate::normals::DEFAULT_CREASE_ANGLE;
+
+/// How much more moving off a boundary or seam costs than moving off a face
+const CONSTRAINT_WEIGHT: f64 = 1000.0;
+
+/**
+ * A quadric error function, the symmetric 4x4 matrix
+ * [a b c d; b e f g; c f h i; d g i j] stored as [a, b, c, ..., j]. For a
+ * point p, [p 1] Q [p 1]^T is the sum of squared distances to the planes
+ * that were added to Q.
+ */
+type Quadric = [f64; 10];
+
+impl Mesh {
+    /**
+     * Reduce the mesh to at most target_face_count triangles (if possible)
+     * by repeatedly collapsing the edge that changes the shape the least,
+     * as measured by quadric error metrics (Garland and Heckbert). Each edge
+     * is collapsed onto one of its ends, so no new positions are made.
+     *
+     * Boundary edges and seams (where uvs, materials, objects or groups
+     * change from one face to the next) are kept in place: vertices on them
+     * may only slide along them. Normals are recomputed afterwards rather
+     * than treated as seams. The mesh is triangulated first, and as with
+     * the other operations that need neighbors, it should be welded.
+     */
+    pub fn decimate(&self, target_face_count: usize) -> Self {
+        let mesh = self.triangulate(Triangulation::ShortestDiagonal);
+        let mut decimator = Decimator::new(&mesh);
+        decimator.run(target_face_count);
+        let result = decimator.into_mesh(&mesh);
+
+        if self.has_normals() {
+            result.with_smooth_normals(DEFAULT_CREASE_ANGLE)
+        } else {
+            result
+        }
+    }
+}
+
+/// Collapsing the edge from one vertex onto another
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    /// The versions of both vertices when the cost was computed
+    versions: [u32; 2],
+    /// Whether this is the more expensive direction, tried because the
+    /// cheaper one wasn't allowed
+    reversed: bool,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Reversed so the cheapest collapse is at the top of the BinaryHeap
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+            .then_with(|| other.from.cmp(&self.from))
+            .then_with(|| other.to.cmp(&self.to))
+    }
+}
+
+struct Decimator {
+    positions: Vec<Vertex>,
+    quadrics: Vec<Quadric>,
+    triangles: Vec<[usize; 3]>,
+    attributes: Vec<FaceAttributes>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    /// Faces around each vertex. Dead faces are removed lazily
+    vertex_faces: Vec<Vec<usize>>,
+    /// Vertices on a boundary or seam
+    constrained: Vec<bool>,
+    /// Vertices where more than two boundary or seam edges meet
+    locked: Vec<bool>,
+    /// Bumped whenever a vertex changes, to spot stale collapses
+    versions: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl Decimator {
+    fn new(mesh: &Mesh) -> Self {
+        let vertex_count = mesh.vertices.len();
+        let triangles: Vec<[usize; 3]> = mesh.faces.iter().map(|face| {
+            match face.indices() {
+                [a, b, c] => [*a, *b, *c],
+                _ => unreachable!("faces are triangulated before decimation")
+            }
+        }).collect();
+
+        let mut vertex_faces: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+        for (f, triangle) in triangles.iter().enumerate() {
+            for vertex in triangle.iter() {
+                vertex_faces[*vertex].push(f);
+            }
+        }
+
+        let mut decimator = Self {
+            positions: mesh.vertices.clone(),
+            quadrics: vec![[0.0; 10]; vertex_count],
+            alive: vec![true; triangles.len()],
+            alive_count: triangles.len(),
+            triangles,
+            attributes: mesh.face_attributes.clone(),
+            vertex_faces,
+            constrained: vec![false; vertex_count],
+            locked: vec![false; vertex_count],
+            versions: vec![0; vertex_count],
+            heap: BinaryHeap::new(),
+        };
+        decimator.init_quadrics();
+
+        let mut edges: Vec<[usize; 2]> = decimator.triangles.iter()
+            .flat_map(|[a, b, c]| vec![[*a, *b], [*b, *c], [*c, *a]])
+            .map(|[a, b]| [a.min(b), a.max(b)])
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        for [a, b] in edges {
+            decimator.push_edge(a, b);
+        }
+
+        decimator
+    }
+
+    /**
+     * Every vertex starts with the planes of the faces around it, weighted
+     * by area. Boundary and seam edges also add a steep plane through the
+     * edge, perpendicular to the face, so moving off them is expensive.
+     */
+    fn init_quadrics(&mut self) {
+        let mut constrained_edges: Vec<[usize; 2]> = Vec::new();
+        for f in 0..self.triangles.len() {
+            let [a, b, c] = self.triangles[f];
+            let normal = self.positions[b].sub(&self.positions[a])
+                .cross(&self.positions[c].sub(&self.positions[a]));
+            let area = 0.5 * normal.length() as f64;
+            let unit_normal = normal.normalize();
+            let quadric = plane_quadric(&unit_normal, &self.positions[a], area);
+            for vertex in [a, b, c].iter() {
+                self.quadrics[*vertex] = add_quadrics(&self.quadrics[*vertex], &quadric);
+            }
+
+            for (start, end) in [(a, b), (b, c), (c, a)].iter() {
+                if !self.is_constrained_edge(*start, *end) {
+                    continue;
+                }
+
+                constrained_edges.push([*start.min(end), *start.max(end)]);
+
+                let edge = self.positions[*end].sub(&self.positions[*start]);
+                let plane_normal = edge.cross(&unit_normal).normalize();
+                let weight = CONSTRAINT_WEIGHT * edge.length() as f64;
+                let quadric = plane_quadric(
+                    &plane_normal, &self.positions[*start], weight);
+                for vertex in [*start, *end].iter() {
+                    self.quadrics[*vertex] =
+                        add_quadrics(&self.quadrics[*vertex], &quadric);
+                }
+            }
+        }
+
+        // Seams are seen from the faces on both sides
+        constrained_edges.sort_unstable();
+        constrained_edges.dedup();
+        let mut constraint_counts = vec![0usize; self.positions.len()];
+        for [a, b] in constrained_edges {
+            constraint_counts[a] += 1;
+            constraint_counts[b] += 1;
+        }
+
+        for (v, count) in constraint_counts.iter().enumerate() {
+            self.constrained[v] = *count > 0;
+            self.locked[v] = *count > 2;
+        }
+    }
+
+    fn run(&mut self, target_face_count: usize) {
+        while self.alive_count > target_face_count {
+            let collapse = match self.heap.pop() {
+                Some(collapse) => collapse,
+                None => break
+            };
+
+            let Collapse { from, to, versions, reversed, .. } = collapse;
+            if versions != [self.versions[from], self.versions[to]] {
+                continue;
+            }
+            if self.can_collapse(from, to) {
+                self.collapse(from, to);
+            } else if !reversed && !self.locked[to] {
+                self.push_collapse(to, from, true);
+            }
+        }
+    }
+
+    /// Queue collapsing the edge in whichever direction is cheaper
+    fn push_edge(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+
+        let quadric = add_quadrics(&self.quadrics[a], &self.quadrics[b]);
+        let cost_to_b = quadric_error(&quadric, &self.positions[b]);
+        let cost_to_a = quadric_error(&quadric, &self.positions[a]);
+        match (self.locked[a], self.locked[b]) {
+            (true, true) => {},
+            (false, true) => self.push_collapse(a, b, false),
+            (true, false) => self.push_collapse(b, a, false),
+            (false, false) => if cost_to_b <= cost_to_a {
+                self.push_collapse(a, b, false)
+            } else {
+                self.push_collapse(b, a, false)
+            }
+        }
+    }
+
+    fn push_collapse(&mut self, from: usize, to: usize, reversed: bool) {
+        let quadric = add_quadrics(&self.quadrics[from], &self.quadrics[to]);
+        self.heap.push(Collapse {
+            cost: quadric_error(&quadric, &self.positions[to]),
+            from,
+            to,
+            versions: [self.versions[from], self.versions[to]],
+            reversed,
+        });
+    }
+
+    fn faces_around(&mut self, vertex: usize) -> Vec<usize> {
+        let alive = &self.alive;
+        self.vertex_faces[vertex].retain(|f| alive[*f]);
+        self.vertex_faces[vertex].clone()
+    }
+
+    fn neighbors(&mut self, vertex: usize) -> Vec<usize> {
+        let mut neighbors: Vec<usize> = self.faces_around(vertex).iter()
+            .flat_map(|f| self.triangles[*f].iter().copied())
+            .filter(|other| *other != vertex)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// The live faces that use the edge between a and b
+    fn edge_faces(&self, a: usize, b: usize) -> Vec<usize> {
+        self.vertex_faces[a].iter()
+            .copied()
+            .filter(|f| self.alive[*f] && self.triangles[*f].contains(&b))
+            .collect()
+    }
+
+    /**
+     * Whether the edge is a boundary (not exactly two faces) or a seam
+     * where the faces on either side have different attributes
+     */
+    fn is_constrained_edge(&self, a: usize, b: usize) -> bool {
+        let faces = self.edge_faces(a, b);
+        let (f, g) = match faces[..] {
+            [f, g] => (f, g),
+            _ => return true
+        };
+
+        let first = &self.attributes[f];
+        let second = &self.attributes[g];
+        if first.material != second.material
+                || first.object != second.object
+                || first.group != second.group {
+            return true;
+        }
+
+        match (&first.uvs, &second.uvs) {
+            (Some(_), Some(_)) => [a, b].iter().any(|vertex| {
+                self.corner_uv(f, *vertex) != self.corner_uv(g, *vertex)
+            }),
+            (None, None) => false,
+            _ => true
+        }
+    }
+
+    fn corner_uv(&self, face: usize, vertex: usize) -> Option<usize> {
+        let corner = self.triangles[face].iter().position(|v| *v == vertex)?;
+        self.attributes[face].uvs.as_ref().map(|uvs| uvs.indices()[corner])
+    }
+
+    fn can_collapse(&mut self, from: usize, to: usize) -> bool {
+        // Vertices on a boundary or seam may only move along it
+        if self.constrained[from] && !self.is_constrained_edge(from, to) {
+            return false;
+        }
+
+        // The link condition: the only vertices next to both ends must be
+        // the ones across from the edge, or the surface gets pinched
+        let shared_faces = self.edge_faces(from, to);
+        if shared_faces.is_empty() {
+            return false;
+        }
+        let mut opposite: Vec<usize> = shared_faces.iter()
+            .flat_map(|f| self.triangles[*f].iter().copied())
+            .filter(|v| *v != from && *v != to)
+            .collect();
+        opposite.sort_unstable();
+        opposite.dedup();
+
+        let to_neighbors = self.neighbors(to);
+        let common: Vec<usize> = self.neighbors(from).into_iter()
+            .filter(|v| to_neighbors.binary_search(v).is_ok())
+            .collect();
+        if common != opposite {
+            return false;
+        }
+
+        // The faces that stay must not flip over or collapse to nothing
+        let target = self.positions[to];
+        for f in self.faces_around(from) {
+            let triangle = self.triangles[f];
+            if triangle.contains(&to) {
+                continue;
+            }
+
+            let corner = |v: usize| self.positions[v];
+            let moved = |v: usize| if v == from { target } else { self.positions[v] };
+            let [a, b, c] = triangle;
+            let before = corner(b).sub(&corner(a)).cross(&corner(c).sub(&corner(a)));
+            let after = moved(b).sub(&moved(a)).cross(&moved(c).sub(&moved(a)));
+            if after.length() <= f32::EPSILON * before.length()
+                    || before.dot(&after) <= 0.0 {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Move from onto to, dropping the faces that used the edge
+    fn collapse(&mut self, from: usize, to: usize) {
+        let faces = self.faces_around(from);
+
+        // Uvs at from are replaced by the uvs at to on the same side of
+        // any seam, which the removed faces tell us
+        let mut uv_replacements: HashMap<usize, usize> = HashMap::new();
+        let mut normal_replacements: HashMap<usize, usize> = HashMap::new();
+        for f in faces.iter() {
+            let triangle = self.triangles[*f];
+            if !triangle.contains(&to) {
+                continue;
+            }
+
+            let from_corner = triangle.iter().position(|v| *v == from).unwrap();
+            let to_corner = triangle.iter().position(|v| *v == to).unwrap();
+            let attributes = &self.attributes[*f];
+            if let Some(uvs) = attributes.uvs.as_ref() {
+                uv_replacements.insert(
+                    uvs.indices()[from_corner], uvs.indices()[to_corner]);
+            }
+            if let Some(normals) = attributes.normals.as_ref() {
+                normal_replacements.insert(
+                    normals.indices()[from_corner], normals.indices()[to_corner]);
+            }
+
+            self.alive[*f] = false;
+            self.alive_count -= 1;
+        }
+
+        let remaining: Vec<usize> = faces.into_iter()
+            .filter(|f| self.alive[*f])
+            .collect();
+        for f in remaining {
+            let corner = self.triangles[f].iter().position(|v| *v == from).unwrap();
+            self.triangles[f][corner] = to;
+
+            let attributes = &mut self.attributes[f];
+            attributes.uvs = attributes.uvs.as_ref()
+                .map(|uvs| replace_corner(uvs, corner, &uv_replacements));
+            attributes.normals = attributes.normals.as_ref()
+                .map(|normals| replace_corner(normals, corner, &normal_replacements));
+            self.vertex_faces[to].push(f);
+        }
+        self.vertex_faces[from].clear();
+
+        self.quadrics[to] = add_quadrics(&self.quadrics[from], &self.quadrics[to]);
+        self.versions[from] += 1;
+        self.versions[to] += 1;
+
+        for neighbor in self.neighbors(to) {
+            self.push_edge(to, neighbor);
+        }
+    }
+
+    /// The remaining faces, with unused vertices removed
+    fn into_mesh(self, original: &Mesh) -> Mesh {
+        let mut new_indices: Vec<Option<usize>> =
+            vec![None; self.positions.len()];
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut result = Mesh {
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..original.clone()
+        };
+
+        let positions = self.positions;
+        let faces = self.triangles.iter().zip(self.attributes);
+        for ((triangle, attributes), alive) in faces.zip(self.alive.iter()) {
+            if !alive {
+                continue;
+            }
+
+            let face = Face::Triangle([0, 1, 2].map(|i| {
+                let vertex = triangle[i];
+                *new_indices[vertex].get_or_insert_with(|| {
+                    vertices.push(positions[vertex]);
+                    vertices.len() - 1
+                })
+            }));
+            result.push_face(face, attributes);
+        }
+
+        result.vertices = vertices;
+        result
+    }
+}
+
+fn replace_corner(corners: &Face, corner: usize, replacements: &HashMap<usize, usize>)
+        -> Face {
+    let mut indices = corners.indices().to_vec();
+    if let Some(replacement) = replacements.get(&indices[corner]) {
+        indices[corner] = *replacement;
+    }
+    Face::from_indices(indices)
+}
+
+/// The quadric for the squared distance to a plane, times weight
+fn plane_quadric(normal: &Vertex, point: &Vertex, weight: f64) -> Quadric {
+    let Vertex([a, b, c]) = normal;
+    let (a, b, c) = (*a as f64, *b as f64, *c as f64);
+    let d = -(a * point.0[0] as f64 + b * point.0[1] as f64 + c * point.0[2] as f64);
+
+    [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d]
+        .map(|value| value * weight)
+}
+
+fn add_quadrics(first: &Quadric, second: &Quadric) -> Quadric {
+    let mut sum = *first;
+    for (value, other) in sum.iter_mut().zip(second.iter()) {
+        *value += other;
+    }
+    sum
+}
+
+fn quadric_error(q: &Quadric, point: &Vertex) -> f64 {
+    let Vertex([x, y, z]) = point;
+    let (x, y, z) = (*x as f64, *y as f64, *z as f64);
+
+    q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+        + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+        + q[7] * z * z + 2.0 * q[8] * z
+        + q[9]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subdivide::Subdivision;
+    use crate::test_meshes::cube;
+
+    fn boundary_positions(mesh: &Mesh) -> Vec<Vertex> {
+        mesh.analyze().boundary_edges.iter()
+            .flat_map(|edge| edge.iter().map(|v| mesh.vertices[*v]).collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn decimated_blob_stays_closed() {
+        let blob = cube().subdivide(Subdivision::CatmullClark, 2);
+        let decimated = blob.decimate(50);
+        assert!(decimated.faces().len() <= 50);
+        assert!(decimated.analyze().is_clean());
+        assert!(decimated.vertices().iter().all(|v| blob.vertices().contains(v)));
+    }
+
+    #[test]
+    fn cube_collapses_to_a_tetrahedron() {
+        let decimated = cube().decimate(4);
+        assert_eq!(decimated.faces().len(), 4);
+        assert_eq!(decimated.vertices().len(), 4);
+        assert!(decimated.analyze().is_clean());
+    }
+
+    #[test]
+    fn boundary_vertices_stay_on_the_boundary() {
+        let mut square = cube();
+        square.faces.truncate(1);
+        square.face_attributes.truncate(1);
+        let grid = square.subdivide(Subdivision::CatmullClark, 2);
+
+        let decimated = grid.decimate(8);
+        assert!(decimated.faces().len() <= 8);
+        assert!(decimated.vertices().iter().all(|Vertex([x, _, _])| *x == 0.0));
+        let boundary = boundary_positions(&grid);
+        assert!(boundary_positions(&decimated).iter().all(|v| boundary.contains(v)));
+    }
+
+    #[test]
+    fn meshes_already_small_enough_are_left_alone() {
+        let decimated = cube().decimate(100);
+        assert_eq!(decimated.faces().len(), 12);
+        assert!((decimated.volume() - 1.0).abs() < 1e-5);
+    }
+}
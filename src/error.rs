@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+use std::io;
+
+use crate::grid::GridError;
+
+/// Errors from reading or writing mesh files, or from generating a grid
+#[derive(Debug)]
+pub enum MeshError {
+    /// The file could not be opened, read or written
+    Io {
+        file: String,
+        source: io::Error,
+    },
+    /// A line of the file could not be parsed. Line numbers start at 1.
+    Parse {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+    /// The grid could not be generated
+    Grid(GridError),
+}
+
+impl MeshError {
+    pub fn io(file: &str, source: io::Error) -> Self {
+        MeshError::Io {
+            file: file.to_string(),
+            source,
+        }
+    }
+
+    pub fn parse(file: &str, line: usize, reason: String) -> Self {
+        MeshError::Parse {
+            file: file.to_string(),
+            line,
+            reason,
+        }
+    }
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            MeshError::Io { file, source }
+                => write!(f, "{}: {}", file, source),
+            MeshError::Parse { file, line, reason }
+                => write!(f, "{}:{}: {}", file, line, reason),
+            MeshError::Grid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for MeshError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MeshError::Io { source, .. } => Some(source),
+            MeshError::Parse { .. } => None,
+            MeshError::Grid(error) => Some(error),
+        }
+    }
+}
+
+impl From<GridError> for MeshError {
+    fn from(error: GridError) -> Self {
+        MeshError::Grid(error)
+    }
+}
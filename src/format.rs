@@ -0,0 +1,314 @@
+use std::path::Path;
+
+use std::collections::HashMap;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::Mesh;
+use crate::error::MeshError;
+use crate::ply::PlyAttributes;
+use crate::gltf::NormalSource;
+use crate::color::{Color, srgb_bytes};
+use crate::parts::Parts;
+use crate::grid::CellID;
+
+/// A mesh with vertices split for PLY, and the attributes of the new vertices
+struct SplitVertices {
+    mesh: Mesh,
+    normals: Option<Vec<Vertex>>,
+    colors: Option<Vec<[u8; 3]>>,
+}
+
+type VertexKey = (usize, Option<NormalSource>, Option<[u8; 3]>);
+
+/// Formats that come in both a text and a binary flavor
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Binary,
+}
+
+/// The file formats a Mesh can be saved as
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FileFormat {
+    Obj,
+    Stl(Encoding),
+    Ply(Encoding),
+    Gltf,
+    Glb,
+    ThreeMf,
+}
+
+impl FileFormat {
+    /**
+     * Pick a format from a file extension (case insensitive). Formats that
+     * have both encodings default to binary. Returns None for unsupported
+     * extensions.
+     */
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "obj" => Some(FileFormat::Obj),
+            "stl" => Some(FileFormat::Stl(Encoding::Binary)),
+            "ply" => Some(FileFormat::Ply(Encoding::Binary)),
+            "gltf" => Some(FileFormat::Gltf),
+            "glb" => Some(FileFormat::Glb),
+            "3mf" => Some(FileFormat::ThreeMf),
+            _ => None
+        }
+    }
+
+    /// Switch to the text encoding of this format if it has one
+    pub fn ascii(self) -> Self {
+        match self {
+            FileFormat::Stl(_) => FileFormat::Stl(Encoding::Ascii),
+            FileFormat::Ply(_) => FileFormat::Ply(Encoding::Ascii),
+            _ => self
+        }
+    }
+}
+
+/// Settings for Mesh::save() that only apply to some formats
+#[derive(Clone, Debug)]
+pub struct SaveOptions {
+    /**
+     * How to split the mesh into separate objects, for formats that
+     * support them. By default there is one part per OBJ object.
+     */
+    pub parts: Option<Parts>,
+    /// Millimeters per grid cell, for formats with real-world units
+    pub cell_size: f32,
+    /// Named integer properties for each face, like a strand id, for
+    /// formats that can store them
+    pub face_properties: Vec<(String, Vec<i32>)>,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            parts: None,
+            cell_size: 10.0,
+            face_properties: Vec::new(),
+        }
+    }
+}
+
+impl Mesh {
+    pub fn save<P: AsRef<Path>>(
+            &self, fname: P, format: FileFormat, options: &SaveOptions)
+            -> Result<(), MeshError> {
+        let parts = match &options.parts {
+            Some(parts) => parts.clone(),
+            None => self.default_parts()
+        };
+
+        match format {
+            FileFormat::Obj => self.save_obj_file(fname),
+            FileFormat::Stl(encoding) => self.save_stl(fname, encoding),
+            FileFormat::Ply(encoding) => {
+                let properties = self.provenance_properties();
+                let face_colors = self.face_colors(&parts);
+                let split = if self.has_normals() || face_colors.is_some() {
+                    Some(self.split_vertices(face_colors.as_deref()))
+                } else {
+                    None
+                };
+
+                let mut attributes = PlyAttributes::default();
+                if let Some(split) = split.as_ref() {
+                    attributes.normals = split.normals.as_deref();
+                    attributes.colors = split.colors.as_deref();
+                }
+                if let Some([x, y, z, kind]) = &properties {
+                    attributes.face_properties = vec![
+                        ("cell_x", x), ("cell_y", y), ("cell_z", z),
+                        ("cell_kind", kind)
+                    ];
+                }
+                for (name, values) in options.face_properties.iter() {
+                    attributes.face_properties.push((name, values));
+                }
+
+                let mesh = split.as_ref().map_or(self, |split| &split.mesh);
+                mesh.save_ply(fname, encoding, &attributes)
+            },
+            FileFormat::Gltf => self.save_gltf(fname, &parts),
+            FileFormat::Glb => self.save_glb(fname, &parts),
+            FileFormat::ThreeMf
+                => self.save_3mf(fname, &parts, options.cell_size),
+        }
+    }
+
+    /**
+     * Per-face cell coordinates and kind (numbered in the order CellKind
+     * lists them) for formats that can store integer properties. Faces with
+     * no provenance get -1. Returns None if no face has provenance.
+     */
+    fn provenance_properties(&self) -> Option<[Vec<i32>; 4]> {
+        if self.face_attributes.iter().all(|attributes| attributes.cell.is_none()) {
+            return None;
+        }
+
+        let mut properties: [Vec<i32>; 4] = Default::default();
+        for attributes in self.face_attributes.iter() {
+            let values = match (attributes.cell, attributes.kind) {
+                (Some(CellID(i, j, k)), Some(kind))
+                    => [i as i32, j as i32, k as i32, kind as i32],
+                _ => [-1; 4]
+            };
+            for (property, value) in properties.iter_mut().zip(values.iter()) {
+                property.push(*value);
+            }
+        }
+
+        Some(properties)
+    }
+
+    /**
+     * The color of each face, for formats that store colors per vertex:
+     * the color of its part, or else the color of its material. Faces
+     * with neither are white. None if no face has a color.
+     */
+    fn face_colors(&self, parts: &Parts) -> Option<Vec<[u8; 3]>> {
+        let white = [1.0; 3];
+        if let Some(colors) = parts.colors.as_ref() {
+            return Some(parts.face_parts.iter()
+                .map(|part| srgb_bytes(&colors[*part]))
+                .collect());
+        }
+
+        let colors: Vec<Option<Color>> = self.face_attributes.iter()
+            .map(|attributes| attributes.material
+                .and_then(|material| self.material_color(&self.materials[material])))
+            .collect();
+        if colors.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(colors.iter()
+            .map(|color| srgb_bytes(&color.unwrap_or(white)))
+            .collect())
+    }
+
+    /**
+     * PLY normals and colors are per vertex, so vertices are duplicated
+     * wherever the faces around them have different normals, like along
+     * creases or everywhere with flat normals, or different colors. Faces
+     * keep their order. Faces without normals use their flat normal.
+     */
+    fn split_vertices(&self, face_colors: Option<&[[u8; 3]]>) -> SplitVertices {
+        let with_normals = self.has_normals();
+        let mut mesh = Self {
+            vertices: Vec::new(),
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            normals: Vec::new(),
+            ..self.clone()
+        };
+        let mut normals: Vec<Vertex> = Vec::new();
+        let mut colors: Vec<[u8; 3]> = Vec::new();
+        // Old vertex, normal and color of each new vertex
+        let mut lookup: HashMap<VertexKey, usize> = HashMap::new();
+
+        for (f, face) in self.faces.iter().enumerate() {
+            let corner_normals = self.face_attributes[f].normals.as_ref();
+            let color = face_colors.map(|colors| colors[f]);
+            let indices: Vec<usize> = face.indices().iter().enumerate()
+                .map(|(corner, vertex)| {
+                    let source = match corner_normals {
+                        _ if !with_normals => None,
+                        Some(corners)
+                            => Some(NormalSource::Corner(corners.indices()[corner])),
+                        None => Some(NormalSource::Face(f))
+                    };
+                    *lookup.entry((*vertex, source, color)).or_insert_with(|| {
+                        mesh.vertices.push(self.vertices[*vertex]);
+                        normals.extend(source.map(|source| match source {
+                            NormalSource::Corner(n) => self.normals[n],
+                            NormalSource::Face(f) => self.face_normal(f),
+                        }));
+                        colors.extend(color);
+                        mesh.vertices.len() - 1
+                    })
+                })
+                .collect();
+
+            let mut attributes = self.face_attributes[f].clone();
+            attributes.normals = None;
+            mesh.push_face(Face::from_indices(indices), attributes);
+        }
+
+        SplitVertices {
+            mesh,
+            normals: Some(normals).filter(|_| with_normals),
+            colors: face_colors.map(|_| colors),
+        }
+    }
+
+    /**
+     * How to split the mesh into objects for formats that support them when
+     * the caller doesn't say otherwise
+     */
+    fn default_parts(&self) -> Parts {
+        if self.objects.is_empty() {
+            Parts::single("knot", self.faces.len())
+        } else {
+            self.parts_by_object()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_meshes::cube;
+
+    #[test]
+    fn vertices_are_split_between_colors() {
+        let mesh = cube();
+        let red = [255, 0, 0];
+        let white = [255; 3];
+        let colors: Vec<[u8; 3]> = (0..6)
+            .map(|f| if f == 0 { red } else { white })
+            .collect();
+
+        // The corners of the red side get a second, white copy
+        let split = mesh.split_vertices(Some(&colors));
+        let split_colors = split.colors.unwrap();
+        assert_eq!(split.mesh.vertices().len(), 12);
+        assert_eq!(split_colors.len(), 12);
+        assert_eq!(split.normals, None);
+        let faces = split.mesh.faces().iter().zip(mesh.faces().iter());
+        for (f, (face, original)) in faces.enumerate() {
+            for (new, old) in face.indices().iter().zip(original.indices().iter()) {
+                assert_eq!(split_colors[*new], colors[f]);
+                assert_eq!(split.mesh.vertices()[*new], mesh.vertices()[*old]);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_of_one_color_share_vertices() {
+        let split = cube().split_vertices(Some(&[[255; 3]; 6]));
+        assert_eq!(split.mesh.vertices().len(), 8);
+        assert_eq!(split.colors.map(|colors| colors.len()), Some(8));
+    }
+
+    #[test]
+    fn vertices_are_split_between_normals() {
+        // Every corner of a flat shaded cube has three normals
+        let flat = cube().with_flat_normals();
+        let split = flat.split_vertices(None);
+        let normals = split.normals.unwrap();
+        assert_eq!(split.mesh.vertices().len(), 24);
+        assert_eq!(normals.len(), 24);
+        assert_eq!(split.colors, None);
+        for (f, face) in split.mesh.faces().iter().enumerate() {
+            for vertex in face.indices().iter() {
+                assert_eq!(normals[*vertex], flat.face_normal(f));
+            }
+        }
+
+        // With the edges smoothed, each corner has one
+        let smooth = cube().with_smooth_normals(120.0).split_vertices(None);
+        assert_eq!(smooth.mesh.vertices().len(), 8);
+        assert_eq!(smooth.normals.map(|normals| normals.len()), Some(8));
+    }
+}
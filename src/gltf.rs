@@ -0,0 +1,485 @@
+use std::io::{self, BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+use std::collections::HashMap;
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+use crate::measure::bounding_box;
+use crate::parts::Parts;
+use crate::error::MeshError;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const TRIANGLES: u32 = 4;
+
+/**
+ * Vertex buffers for one part of the mesh. glTF normals are per vertex, so
+ * mesh vertices are duplicated wherever the faces around them need
+ * different normals.
+ */
+struct Primitive {
+    name: String,
+    positions: Vec<Vertex>,
+    normals: Vec<Vertex>,
+    indices: Vec<u32>,
+    material: Option<usize>,
+}
+
+/// Where an exported vertex's normal comes from, for formats with per-vertex
+/// normals
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum NormalSource {
+    /// An entry of Mesh::normals
+    Corner(usize),
+    /// The flat normal of a face
+    Face(usize),
+}
+
+impl Mesh {
+    /**
+     * Save the mesh as a .gltf JSON file with the binary buffer embedded
+     * as a base64 data URI, so the result is a single self-contained file.
+     * Each part becomes its own node. If the parts have colors, each part
+     * also gets a material.
+     */
+    pub fn save_gltf<P: AsRef<Path>>(&self, fname: P, parts: &Parts)
+            -> Result<(), MeshError> {
+        let fname = fname.as_ref().display().to_string();
+        let file = File::create(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        self.write_gltf(BufWriter::new(file), parts)
+            .map_err(|error| MeshError::io(&fname, error))
+    }
+
+    /// Save the mesh as a binary .glb file. See save_gltf()
+    pub fn save_glb<P: AsRef<Path>>(&self, fname: P, parts: &Parts)
+            -> Result<(), MeshError> {
+        let fname = fname.as_ref().display().to_string();
+        let file = File::create(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        self.write_glb(BufWriter::new(file), parts)
+            .map_err(|error| MeshError::io(&fname, error))
+    }
+
+    pub fn write_gltf<W: Write>(&self, mut file: W, parts: &Parts)
+            -> io::Result<()> {
+        let primitives = self.gltf_primitives(parts);
+        check_finite(&primitives, parts)?;
+        let buffer = build_buffer(&primitives);
+        let uri = format!(
+            "data:application/octet-stream;base64,{}", base64(&buffer));
+        let json = build_json(&primitives, parts, buffer.len(), Some(&uri));
+
+        file.write_all(json.as_bytes())?;
+        file.flush()
+    }
+
+    pub fn write_glb<W: Write>(&self, mut file: W, parts: &Parts)
+            -> io::Result<()> {
+        let primitives = self.gltf_primitives(parts);
+        check_finite(&primitives, parts)?;
+        let mut buffer = build_buffer(&primitives);
+        let mut json = build_json(&primitives, parts, buffer.len(), None)
+            .into_bytes();
+
+        // Chunks must be 4-byte aligned. The JSON chunk is padded with
+        // spaces, the binary chunk with zeros
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        while !buffer.len().is_multiple_of(4) {
+            buffer.push(0);
+        }
+
+        let has_buffer = !buffer.is_empty();
+        let mut total_length = 12 + 8 + json.len();
+        if has_buffer {
+            total_length += 8 + buffer.len();
+        }
+
+        // header
+        file.write_all(b"glTF")?;
+        file.write_all(&2u32.to_le_bytes())?;
+        file.write_all(&(total_length as u32).to_le_bytes())?;
+
+        file.write_all(&(json.len() as u32).to_le_bytes())?;
+        file.write_all(b"JSON")?;
+        file.write_all(&json)?;
+
+        if has_buffer {
+            file.write_all(&(buffer.len() as u32).to_le_bytes())?;
+            file.write_all(b"BIN\0")?;
+            file.write_all(&buffer)?;
+        }
+
+        file.flush()
+    }
+
+    /// Build vertex buffers for each non-empty part
+    fn gltf_primitives(&self, parts: &Parts) -> Vec<Primitive> {
+        let mut primitives: Vec<Primitive> = Vec::new();
+        for (part, faces) in parts.faces_per_part().iter().enumerate() {
+            if faces.is_empty() {
+                continue;
+            }
+
+            let mut primitive = Primitive {
+                name: parts.names[part].clone(),
+                positions: Vec::new(),
+                normals: Vec::new(),
+                indices: Vec::new(),
+                material: parts.colors.as_ref().map(|_| part),
+            };
+
+            let mut lookup: HashMap<(usize, NormalSource), u32> =
+                HashMap::new();
+            for face_index in faces.iter() {
+                let face = &self.faces[*face_index];
+                let corner_normals = &self.face_attributes[*face_index].normals;
+                let corners = face.indices();
+                let flat_normal = self.face_normal(*face_index);
+
+                // Split into a fan of triangles, keeping track of which
+                // corner of the face each vertex came from
+                for i in 1..corners.len() - 1 {
+                    for corner in [0, i, i + 1].iter() {
+                        let vertex = &corners[*corner];
+                        let source = match corner_normals {
+                            Some(normals)
+                                => NormalSource::Corner(normals.indices()[*corner]),
+                            None => NormalSource::Face(*face_index)
+                        };
+
+                        let index = *lookup.entry((*vertex, source))
+                            .or_insert_with(|| {
+                                let normal = match source {
+                                    NormalSource::Corner(i) => self.normals[i],
+                                    NormalSource::Face(_) => flat_normal,
+                                };
+                                primitive.positions.push(self.vertices[*vertex]);
+                                primitive.normals.push(unit_normal(&normal));
+                                (primitive.positions.len() - 1) as u32
+                            });
+                        primitive.indices.push(index);
+                    }
+                }
+            }
+
+            primitives.push(primitive);
+        }
+
+        primitives
+    }
+}
+
+/// glTF requires unit normals, so zero normals get an arbitrary direction
+fn unit_normal(normal: &Vertex) -> Vertex {
+    let result = normal.normalize();
+    if result.length() == 0.0 {
+        Vertex([0.0, 0.0, 1.0])
+    } else {
+        result
+    }
+}
+
+/**
+ * JSON has no NaN or infinity, so the positions, whose bounds go in the
+ * JSON, and the colors must be finite
+ */
+fn check_finite(primitives: &[Primitive], parts: &Parts) -> io::Result<()> {
+    let positions = primitives.iter()
+        .flat_map(|primitive| primitive.positions.iter())
+        .flat_map(|Vertex(components)| components.iter());
+    let colors = parts.colors.iter().flatten().flat_map(|color| color.iter());
+    let mut values = positions.chain(colors);
+    if values.all(|value| value.is_finite()) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "glTF can't store NaN or infinite coordinates or colors"))
+    }
+}
+
+/**
+ * Pack positions, normals and indices of every primitive into one buffer,
+ * in that order
+ */
+fn build_buffer(primitives: &[Primitive]) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::new();
+    for primitive in primitives.iter() {
+        for Vertex(components) in primitive.positions.iter() {
+            for component in components.iter() {
+                buffer.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        for Vertex(components) in primitive.normals.iter() {
+            for component in components.iter() {
+                buffer.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        for index in primitive.indices.iter() {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+    }
+
+    buffer
+}
+
+/**
+ * Build the glTF JSON document. The layout of the buffer must match
+ * build_buffer(). uri is given for .gltf files and omitted for .glb, where
+ * the buffer is stored in the binary chunk instead.
+ */
+fn build_json(
+        primitives: &[Primitive],
+        parts: &Parts,
+        buffer_length: usize,
+        uri: Option<&str>) -> String {
+    let mut buffer_views: Vec<String> = Vec::new();
+    let mut accessors: Vec<String> = Vec::new();
+    let mut meshes: Vec<String> = Vec::new();
+    let mut nodes: Vec<String> = Vec::new();
+
+    let mut offset = 0;
+    for (i, primitive) in primitives.iter().enumerate() {
+        let vertex_count = primitive.positions.len();
+        let vector_bytes = vertex_count * 12;
+        let index_bytes = primitive.indices.len() * 4;
+
+        // positions
+        let (min, max) = bounding_box(&primitive.positions);
+        buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, vector_bytes, ARRAY_BUFFER));
+        accessors.push(format!(
+            concat!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"#,
+                r#""type":"VEC3","min":{},"max":{}}}"#),
+            buffer_views.len() - 1, FLOAT, vertex_count,
+            json_vector(&min), json_vector(&max)));
+        let position_accessor = accessors.len() - 1;
+        offset += vector_bytes;
+
+        // normals
+        buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, vector_bytes, ARRAY_BUFFER));
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3"}}"#,
+            buffer_views.len() - 1, FLOAT, vertex_count));
+        let normal_accessor = accessors.len() - 1;
+        offset += vector_bytes;
+
+        // indices
+        buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, index_bytes, ELEMENT_ARRAY_BUFFER));
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"SCALAR"}}"#,
+            buffer_views.len() - 1, UNSIGNED_INT, primitive.indices.len()));
+        let index_accessor = accessors.len() - 1;
+        offset += index_bytes;
+
+        let material = match primitive.material {
+            Some(material) => format!(r#","material":{}"#, material),
+            None => String::new()
+        };
+        meshes.push(format!(
+            concat!(
+                r#"{{"name":{},"primitives":[{{"attributes":"#,
+                r#"{{"POSITION":{},"NORMAL":{}}},"indices":{}{},"mode":{}}}]}}"#),
+            json_string(&primitive.name), position_accessor, normal_accessor,
+            index_accessor, material, TRIANGLES));
+        nodes.push(format!(
+            r#"{{"name":{},"mesh":{}}}"#, json_string(&primitive.name), i));
+    }
+
+    // An empty list of nodes isn't allowed, so an empty mesh gets a scene
+    // with no nodes property
+    let scene = if nodes.is_empty() {
+        String::from("{}")
+    } else {
+        let node_indices: Vec<String> = (0..nodes.len())
+            .map(|i| i.to_string())
+            .collect();
+        format!(r#"{{"nodes":[{}]}}"#, node_indices.join(","))
+    };
+
+    let mut json = String::from(
+        r#"{"asset":{"version":"2.0","generator":"celtic-knots-3d"},"#);
+    json.push_str(&format!(r#""scene":0,"scenes":[{}]"#, scene));
+
+    if !nodes.is_empty() {
+        json.push_str(&format!(r#","nodes":[{}]"#, nodes.join(",")));
+        json.push_str(&format!(r#","meshes":[{}]"#, meshes.join(",")));
+        json.push_str(&format!(r#","accessors":[{}]"#, accessors.join(",")));
+        json.push_str(&format!(
+            r#","bufferViews":[{}]"#, buffer_views.join(",")));
+
+        let uri_property = match uri {
+            Some(uri) => format!(r#","uri":{}"#, json_string(uri)),
+            None => String::new()
+        };
+        json.push_str(&format!(
+            r#","buffers":[{{"byteLength":{}{}}}]"#,
+            buffer_length, uri_property));
+    }
+
+    if let Some(colors) = &parts.colors {
+        let materials: Vec<String> = colors.iter().enumerate()
+            .map(|(i, [r, g, b])| format!(
+                concat!(
+                    r#"{{"name":{},"pbrMetallicRoughness":{{"#,
+                    r#""baseColorFactor":[{},{},{},1],"#,
+                    r#""metallicFactor":0,"roughnessFactor":0.8}}}}"#),
+                json_string(&parts.names[i]), r, g, b))
+            .collect();
+        json.push_str(&format!(r#","materials":[{}]"#, materials.join(",")));
+    }
+
+    json.push('}');
+    json
+}
+
+fn json_vector(vector: &Vertex) -> String {
+    let Vertex([x, y, z]) = vector;
+    format!("[{},{},{}]", x, y, z)
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                result.push_str(&format!("\\u{:04x}", c as u32))
+            },
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        result.push(ALPHABET[(triple >> 18) as usize & 63] as char);
+        result.push(ALPHABET[(triple >> 12) as usize & 63] as char);
+        if chunk.len() > 1 {
+            result.push(ALPHABET[(triple >> 6) as usize & 63] as char);
+        } else {
+            result.push('=');
+        }
+        if chunk.len() > 2 {
+            result.push(ALPHABET[triple as usize & 63] as char);
+        } else {
+            result.push('=');
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word) as usize
+    }
+
+    #[test]
+    fn base64_matches_the_rfc_examples() {
+        let examples = [
+            ("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy"),
+        ];
+        for (data, encoded) in examples.iter() {
+            assert_eq!(base64(data.as_bytes()), *encoded);
+        }
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("knot"), r#""knot""#);
+        assert_eq!(json_string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\knots"), r#""C:\\knots""#);
+        assert_eq!(json_string("a\nb"), r#""a\u000ab""#);
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned_and_add_up() {
+        // Names of every length mod 4, so the JSON needs each amount of
+        // padding
+        for name in ["a", "ab", "abc", "abcd"].iter() {
+            let mut bytes: Vec<u8> = Vec::new();
+            cube().write_glb(&mut bytes, &Parts::single(name, 6)).unwrap();
+
+            assert_eq!(&bytes[..4], b"glTF");
+            assert_eq!(u32_at(&bytes, 4), 2);
+            assert_eq!(u32_at(&bytes, 8), bytes.len());
+
+            let json_length = u32_at(&bytes, 12);
+            assert_eq!(json_length % 4, 0);
+            assert_eq!(&bytes[16..20], b"JSON");
+            let json = std::str::from_utf8(&bytes[20..20 + json_length]).unwrap();
+            assert!(json.trim_end().ends_with('}'));
+            assert!(json.contains(&format!(r#""name":"{}""#, name)));
+
+            let bin = 20 + json_length;
+            let bin_length = u32_at(&bytes, bin);
+            assert_eq!(bin_length % 4, 0);
+            assert_eq!(&bytes[bin + 4..bin + 8], b"BIN\0");
+            assert_eq!(bin + 8 + bin_length, bytes.len());
+            assert!(json.contains(&format!(r#""byteLength":{}}}"#, bin_length)));
+        }
+    }
+
+    #[test]
+    fn gltf_embeds_the_buffer() {
+        let mut bytes: Vec<u8> = Vec::new();
+        cube().write_gltf(&mut bytes, &Parts::single("knot", 6)).unwrap();
+        let json = String::from_utf8(bytes).unwrap();
+
+        // Flat shaded, each of the 6 quads has its own 4 vertices with a
+        // position and normal, and 2 triangles of indices
+        let length = 24 * 2 * 12 + 6 * 6 * 4;
+        let prefix = "data:application/octet-stream;base64,";
+        let start = json.find(prefix).unwrap() + prefix.len();
+        let end = start + json[start..].find('"').unwrap();
+        assert_eq!(end - start, length / 3 * 4);
+        assert!(json.contains(&format!(r#""byteLength":{},"#, length)));
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        let mut mesh = cube();
+        mesh.vertices[3] = Vertex([f32::NAN, 0.0, 0.0]);
+        let error = mesh.write_gltf(Vec::new(), &Parts::single("knot", 6))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let mut parts = Parts::single("knot", 6);
+        parts.colors = Some(vec![[f32::INFINITY, 0.0, 0.0]]);
+        let error = cube().write_glb(Vec::new(), &parts).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
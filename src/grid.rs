@@ -0,0 +1,496 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::mesh::Mesh;
+use crate::cube_rotations::CubeRotation;
+use crate::tileset::Tileset;
+
+/// Whether twist cells should sometimes be replaced with mirrors (untwists)
+#[derive(Copy, Clone, Debug)]
+pub enum MirrorPolicy {
+    Disabled,
+    /// Each twist cell becomes a mirror with the given probability
+    Random(f64),
+}
+
+impl MirrorPolicy {
+    fn choose_mirror<R: Rng>(self, rng: &mut R) -> bool {
+        match self {
+            MirrorPolicy::Disabled => false,
+            MirrorPolicy::Random(chance) => rng.gen_bool(chance),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CellID(pub u32, pub u32, pub u32);
+
+/// Which kind of tile a cell of the grid holds
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CellKind {
+    Twist,
+    /// A mirror, where the strands bounce off instead of crossing
+    Untwist,
+    Connector,
+    /// A twist cell on a face of the grid
+    EndCap,
+    /// A twist cell on an edge of the grid
+    EdgeCap,
+}
+
+impl CellKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CellKind::Twist => "twist",
+            CellKind::Untwist => "untwist",
+            CellKind::Connector => "connector",
+            CellKind::EndCap => "end_cap",
+            CellKind::EdgeCap => "edge_cap",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum RangeComparison {
+    Min,
+    Between,
+    Max
+}
+
+#[derive(Debug)]
+struct BoundsClassification(
+    RangeComparison, 
+    RangeComparison,
+    RangeComparison
+);
+
+#[derive(Copy, Clone, Debug)]
+struct Range(u32, u32);
+
+/// The range of cell indices along each axis of the grid
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds(Range, Range, Range);
+
+impl Bounds {
+    pub fn new(n: u32, m: u32, p: u32) -> Self {
+        Bounds(
+            Range(0, n - 1),
+            Range(0, m - 1),
+            Range(0, p - 1)
+        )
+    }
+}
+
+fn compare_range(x: u32, range: Range) -> RangeComparison {
+    let Range(min_val, max_val) = range;
+    use RangeComparison::{Min, Max, Between};
+    if x == min_val {
+        Min
+    } else if x == max_val {
+        Max
+    } else {
+        Between
+    }
+}
+
+fn classify_bounds(cell_id: CellID, bounds: Bounds) -> BoundsClassification {
+    let CellID(i, j, k) = cell_id;
+    let Bounds(x_range, y_range, z_range) = bounds;
+    BoundsClassification(
+        compare_range(i, x_range),
+        compare_range(j, y_range),
+        compare_range(k, z_range),
+    )
+}
+
+fn twist_rotation(k: u32) -> CubeRotation {
+    let layer_parity = k % 2;
+    if layer_parity == 0 {
+        CubeRotation::rx()
+    } else {
+        CubeRotation::identity()
+    }
+}
+
+fn orient_twist_cell<R: Rng>(
+        tileset: &Tileset, cell_id: CellID, mirrors: MirrorPolicy, rng: &mut R)
+        -> Mesh {
+    let (twist_tile, kind) = if mirrors.choose_mirror(rng) {
+        (tileset.untwist(), CellKind::Untwist)
+    } else {
+        (tileset.quad_twist(), CellKind::Twist)
+    };
+
+    let CellID(i, j, k) = cell_id;
+    let rotation = twist_rotation(k);
+    twist_tile
+        .rotate(&rotation)
+        .translate(&[i as f32, j as f32, k as f32])
+        .with_provenance(cell_id, kind)
+}
+
+fn generate_end_cap(tileset: &Tileset, cell_id: CellID, rotation: CubeRotation) -> Mesh {
+    let CellID(i, j, k) = cell_id;
+    tileset.end_cap()
+        .rotate(&rotation)
+        .translate(&[i as f32, j as f32, k as f32])
+        .with_provenance(cell_id, CellKind::EndCap)
+}
+
+fn generate_edge_cap(tileset: &Tileset, cell_id: CellID, rotation: CubeRotation) -> Mesh {
+    let CellID(i, j, k) = cell_id;
+    tileset.edge_cap()
+        .rotate(&rotation)
+        .translate(&[i as f32, j as f32, k as f32])
+        .with_provenance(cell_id, CellKind::EdgeCap)
+}
+
+fn generate_twist_cell<R: Rng>(
+        tileset: &Tileset,
+        cell_id: CellID,
+        bounds: Bounds,
+        mirrors: MirrorPolicy,
+        rng: &mut R) -> Mesh {
+    let classification = classify_bounds(cell_id, bounds);
+    use RangeComparison::{Min, Max, Between};
+    match classification {
+        BoundsClassification(Min, Between, Between) 
+            => generate_end_cap(tileset, cell_id, CubeRotation::ry3()),
+        BoundsClassification(Max, Between, Between)
+            => generate_end_cap(tileset, cell_id, CubeRotation::ry()),
+        BoundsClassification(Between, Min, Between)
+            => generate_end_cap(tileset, cell_id, CubeRotation::rx()),
+        BoundsClassification(Between, Max, Between)
+            => generate_end_cap(tileset, cell_id, CubeRotation::rx3()),
+        BoundsClassification(Between, Between, Min)
+            => generate_end_cap(tileset, cell_id, CubeRotation::ry2()),
+        BoundsClassification(Between, Between, Max)
+            => generate_end_cap(tileset, cell_id, CubeRotation::identity()),
+        BoundsClassification(Min, Min, Between)
+            => generate_edge_cap(tileset, cell_id, CubeRotation::identity()),
+        BoundsClassification(Min, Max, Between)
+            => generate_edge_cap(tileset, cell_id, CubeRotation::rz3()),
+        BoundsClassification(Max, Min, Between)
+            => generate_edge_cap(tileset, cell_id, CubeRotation::rz()),
+        BoundsClassification(Max, Max, Between)
+            => generate_edge_cap(tileset, cell_id, CubeRotation::rz2()),
+        _ => orient_twist_cell(tileset, cell_id, mirrors, rng)
+    }
+}
+
+fn generate_connector(tileset: &Tileset, rotation: CubeRotation) -> Mesh {
+    tileset.connector()
+        .rotate(&rotation)
+}
+
+fn generate_connector_cell(
+        tileset: &Tileset,
+        cell_id: CellID, 
+        rotation: CubeRotation, 
+        bounds: Bounds) -> Mesh {
+
+    let connector = generate_connector(tileset, rotation);
+
+    let classification = classify_bounds(cell_id, bounds);
+    use RangeComparison::{Min, Max, Between};
+    let clipped_connector = match classification {
+        BoundsClassification(Min, Between, Between) 
+            => connector.simple_clip([-1.0, 0.0, 0.0]),
+        BoundsClassification(Max, Between, Between)
+            => connector.simple_clip([1.0, 0.0, 0.0]),
+        BoundsClassification(Between, Min, Between)
+            => connector.simple_clip([0.0, -1.0, 0.0]),
+        BoundsClassification(Between, Max, Between)
+            => connector.simple_clip([0.0, 1.0, 0.0]),
+        BoundsClassification(Between, Between, Min)
+            => connector.simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Between, Between, Max)
+            => connector.simple_clip([0.0, 0.0, 1.0]),
+        // 12 edges
+        BoundsClassification(Min, Between, Min) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Min, Between, Max) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Max, Between, Min) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Max, Between, Max) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Between, Min, Min) 
+            => connector
+                .simple_clip([0.0, -1.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Between, Min, Max) 
+            => connector
+                .simple_clip([0.0, -1.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Between, Max, Min) 
+            => connector
+                .simple_clip([0.0, 1.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Between, Max, Max) 
+            => connector
+                .simple_clip([0.0, 1.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Min, Min, Between) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, -1.0, 0.0]),
+        BoundsClassification(Min, Max, Between) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, 1.0, 0.0]),
+        BoundsClassification(Max, Min, Between) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, -1.0, 0.0]),
+        BoundsClassification(Max, Max, Between) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, 1.0, 0.0]),
+        // 8 corners
+        BoundsClassification(Min, Min, Min) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, -1.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Min, Min, Max) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, -1.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Min, Max, Min) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, 1.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Min, Max, Max) 
+            => connector
+                .simple_clip([-1.0, 0.0, 0.0])
+                .simple_clip([0.0, 1.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Max, Min, Min) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, -1.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Max, Min, Max) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, -1.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        BoundsClassification(Max, Max, Min) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, 1.0, 0.0])
+                .simple_clip([0.0, 0.0, -1.0]),
+        BoundsClassification(Max, Max, Max) 
+            => connector
+                .simple_clip([1.0, 0.0, 0.0])
+                .simple_clip([0.0, 1.0, 0.0])
+                .simple_clip([0.0, 0.0, 1.0]),
+        _ => connector
+    };
+        
+
+    let CellID(i, j, k) = cell_id;
+    clipped_connector
+        .translate(&[i as f32, j as f32, k as f32])
+        .with_provenance(cell_id, CellKind::Connector)
+}
+
+pub fn generate_cell<R: Rng>(
+        tileset: &Tileset,
+        cell_id: CellID,
+        bounds: Bounds,
+        mirrors: MirrorPolicy,
+        rng: &mut R) -> Mesh {
+    let CellID(i, j, k) = cell_id;
+    let parities = (i % 2, j % 2, k % 2);
+
+    match parities {
+        (1, 1, 0) | (0, 0, 1) 
+            => generate_twist_cell(
+                tileset, cell_id, bounds, mirrors, rng),
+        (1, 0, 1) | (0, 1, 0) 
+            => generate_connector_cell(
+                tileset, cell_id, CubeRotation::identity(), bounds),
+        (0, 0, 0) | (1, 1, 1)
+            => generate_connector_cell(tileset, cell_id, CubeRotation::ry(), bounds),
+        (1, 0, 0) | (0, 1, 1) 
+            => generate_connector_cell(tileset, cell_id, CubeRotation::rz(), bounds),
+        _ => panic!("Invalid cell parity")
+    }
+}
+
+/// Why a grid could not be built
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GridError {
+    /// Every dimension has to be at least 1
+    EmptyDimensions([u32; 3]),
+}
+
+impl Display for GridError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GridError::EmptyDimensions([n, m, p])
+                => write!(f, "grid dimensions {}x{}x{} must all be at least 1",
+                    n, m, p),
+        }
+    }
+}
+
+impl Error for GridError {}
+
+/**
+ * Builder for generating an N x M x P grid of knot cells as a single mesh.
+ *
+ * ```no_run
+ * use celtic_knots_3d::{KnotGridBuilder, MirrorPolicy, Tileset};
+ *
+ * let tileset = Tileset::load("data/sturdy")?;
+ * let mesh = KnotGridBuilder::new(tileset)
+ *     .dimensions(7, 7, 3)
+ *     .mirrors(MirrorPolicy::Random(0.5))
+ *     .seed(42)
+ *     .build()?;
+ * mesh.save_obj_file("grid.obj")?;
+ * # Ok::<(), celtic_knots_3d::MeshError>(())
+ * ```
+ */
+pub struct KnotGridBuilder {
+    tileset: Tileset,
+    dimensions: [u32; 3],
+    mirrors: MirrorPolicy,
+    seed: Option<u64>,
+    weld_tolerance: Option<f32>,
+}
+
+impl KnotGridBuilder {
+    pub fn new(tileset: Tileset) -> Self {
+        Self {
+            tileset,
+            dimensions: [5, 5, 5],
+            mirrors: MirrorPolicy::Disabled,
+            seed: None,
+            weld_tolerance: None,
+        }
+    }
+
+    /// Set the number of cells along each axis. All must be at least 1, or
+    /// build() returns an error.
+    pub fn dimensions(mut self, n: u32, m: u32, p: u32) -> Self {
+        self.dimensions = [n, m, p];
+        self
+    }
+
+    pub fn tileset(mut self, tileset: Tileset) -> Self {
+        self.tileset = tileset;
+        self
+    }
+
+    pub fn mirrors(mut self, mirrors: MirrorPolicy) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Seed the random number generator so the output is reproducible
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /**
+     * Merge vertices closer than tolerance once the grid is generated, so
+     * the strands are connected across cells. See Mesh::weld()
+     */
+    pub fn weld(mut self, tolerance: f32) -> Self {
+        self.weld_tolerance = Some(tolerance);
+        self
+    }
+
+    /**
+     * Generate the grid using a StdRng seeded from the seed if one was
+     * given, or from system entropy otherwise.
+     */
+    pub fn build(&self) -> Result<Mesh, GridError> {
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy()
+        };
+
+        self.build_with_rng(&mut rng)
+    }
+
+    /// Generate the grid using a caller-provided random number generator
+    pub fn build_with_rng<R: Rng>(&self, rng: &mut R) -> Result<Mesh, GridError> {
+        let [n, m, p] = self.dimensions;
+        if n == 0 || m == 0 || p == 0 {
+            return Err(GridError::EmptyDimensions(self.dimensions));
+        }
+        let bounds = Bounds::new(n, m, p);
+
+        let mut grid = Mesh::new();
+        for i in 0..n {
+            for j in 0..m {
+                for k in 0..p {
+                    let cell_id = CellID(i, j, k);
+                    let mesh = generate_cell(
+                        &self.tileset, cell_id, bounds, self.mirrors, rng);
+                    grid.add_geometry(&mesh);
+                }
+            }
+        }
+
+        Ok(match self.weld_tolerance {
+            Some(tolerance) => grid.weld(tolerance),
+            None => grid
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sturdy() -> Tileset {
+        Tileset::load(concat!(env!("CARGO_MANIFEST_DIR"), "/data/sturdy"))
+            .unwrap()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_grid() {
+        let builder = KnotGridBuilder::new(sturdy())
+            .dimensions(3, 2, 2)
+            .mirrors(MirrorPolicy::Random(0.5))
+            .seed(42);
+        let first = builder.build().unwrap();
+        let second = builder.build().unwrap();
+
+        assert!(!first.vertices().is_empty());
+        let positions = |mesh: &Mesh| -> Vec<[f32; 3]> {
+            mesh.vertices().iter().map(|vertex| vertex.0).collect()
+        };
+        assert_eq!(positions(&first), positions(&second));
+        assert_eq!(format!("{:?}", first.faces()), format!("{:?}", second.faces()));
+    }
+
+    #[test]
+    fn empty_dimensions_are_rejected() {
+        for dimensions in [[0, 3, 3], [3, 0, 3], [3, 3, 0]].iter() {
+            let [n, m, p] = *dimensions;
+            let result = KnotGridBuilder::new(sturdy()).dimensions(n, m, p).build();
+            assert_eq!(result.err(), Some(GridError::EmptyDimensions(*dimensions)));
+        }
+    }
+}
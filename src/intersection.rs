@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+use crate::grid::CellID;
+use crate::measure::bounding_box;
+use crate::parts::position_ids;
+
+/// Vertices closer than this to the other triangle's plane count as on it
+const TOUCHING: f32 = 1e-6;
+
+/// Triangles per leaf of the bounding volume hierarchy
+const LEAF_SIZE: usize = 4;
+
+/// Two faces that cut through each other, and the cells they came from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Intersection {
+    /// Face indices, lower first
+    pub faces: [usize; 2],
+    pub cells: [Option<CellID>; 2],
+}
+
+impl Mesh {
+    /**
+     * Find faces that cut through each other, like strands colliding at a
+     * twist. Faces that only touch don't count, and neither do faces that
+     * share a vertex position, since neighbors always touch there. Faces
+     * lying in the same plane only overlap rather than cut through each
+     * other, so they aren't reported either.
+     *
+     * Candidate pairs come from a bounding volume hierarchy over the
+     * triangles, so this is fast enough for whole grids.
+     */
+    pub fn self_intersections(&self) -> Vec<Intersection> {
+        let positions = position_ids(&self.vertices);
+        let mut triangles: Vec<Triangle> = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for corners in face.fan_triangles() {
+                triangles.push(Triangle {
+                    face: f,
+                    positions: corners.map(|v| positions[v]),
+                    corners: corners.map(|v| self.vertices[v]),
+                });
+            }
+        }
+
+        let bvh = Bvh::new(&triangles);
+        let mut pairs: HashSet<[usize; 2]> = HashSet::new();
+        let mut candidates: Vec<usize> = Vec::new();
+        for (i, triangle) in triangles.iter().enumerate() {
+            let (min, max) = bounding_box(&triangle.corners);
+            candidates.clear();
+            bvh.overlapping(&min, &max, &mut candidates);
+
+            for j in candidates.iter() {
+                let other = &triangles[*j];
+                if *j <= i || other.face == triangle.face {
+                    continue;
+                }
+                let neighbors = triangle.positions.iter()
+                    .any(|p| other.positions.contains(p));
+                if neighbors {
+                    continue;
+                }
+
+                let key = [
+                    triangle.face.min(other.face),
+                    triangle.face.max(other.face),
+                ];
+                if !pairs.contains(&key)
+                        && triangles_cross(&triangle.corners, &other.corners) {
+                    pairs.insert(key);
+                }
+            }
+        }
+
+        let mut pairs: Vec<[usize; 2]> = pairs.into_iter().collect();
+        pairs.sort_unstable();
+        pairs.into_iter().map(|faces| Intersection {
+            faces,
+            cells: faces.map(|f| self.face_attributes[f].cell),
+        }).collect()
+    }
+}
+
+struct Triangle {
+    face: usize,
+    /// Vertices by position, so unwelded tiles still count as neighbors
+    positions: [usize; 3],
+    corners: [Vertex; 3],
+}
+
+/// A bounding volume hierarchy over triangles
+struct Bvh {
+    nodes: Vec<Node>,
+    /// Triangle indices, grouped so every leaf has a contiguous range
+    order: Vec<usize>,
+}
+
+struct Node {
+    min: Vertex,
+    max: Vertex,
+    contents: Contents,
+}
+
+enum Contents {
+    /// A range of Bvh::order
+    Leaf(usize, usize),
+    /// Indices of the child nodes
+    Split(usize, usize),
+}
+
+impl Bvh {
+    fn new(triangles: &[Triangle]) -> Self {
+        let bounds: Vec<(Vertex, Vertex)> = triangles.iter()
+            .map(|triangle| bounding_box(&triangle.corners))
+            .collect();
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            order: (0..triangles.len()).collect(),
+        };
+        if !triangles.is_empty() {
+            bvh.build(&bounds, 0, triangles.len());
+        }
+
+        bvh
+    }
+
+    /**
+     * Make a node for a range of the order, splitting it in half along the
+     * longest axis of the triangles' centers. Returns the node's index.
+     */
+    fn build(&mut self, bounds: &[(Vertex, Vertex)], start: usize, end: usize)
+            -> usize {
+        let corners: Vec<Vertex> = self.order[start..end].iter()
+            .flat_map(|t| vec![bounds[*t].0, bounds[*t].1])
+            .collect();
+        let (min, max) = bounding_box(&corners);
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            min,
+            max,
+            contents: Contents::Leaf(start, end),
+        });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        let center = |t: usize| bounds[t].0.add(&bounds[t].1).scale(0.5);
+        let centers: Vec<Vertex> = self.order[start..end].iter()
+            .map(|t| center(*t))
+            .collect();
+        let (Vertex(low), Vertex(high)) = bounding_box(&centers);
+        let axis = longest_axis(&Vertex(high).sub(&Vertex(low)));
+
+        let middle = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(middle - start, |a, b| {
+            let (Vertex(a), Vertex(b)) = (center(*a), center(*b));
+            a[axis].total_cmp(&b[axis])
+        });
+
+        let left = self.build(bounds, start, middle);
+        let right = self.build(bounds, middle, end);
+        self.nodes[index].contents = Contents::Split(left, right);
+        index
+    }
+
+    /// Add the triangles whose boxes overlap the box from min to max
+    fn overlapping(&self, min: &Vertex, max: &Vertex, result: &mut Vec<usize>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !boxes_overlap((&node.min, &node.max), (min, max)) {
+                continue;
+            }
+
+            match node.contents {
+                Contents::Leaf(start, end)
+                    => result.extend_from_slice(&self.order[start..end]),
+                Contents::Split(left, right) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+    }
+}
+
+fn boxes_overlap(
+        (Vertex(min_a), Vertex(max_a)): (&Vertex, &Vertex),
+        (Vertex(min_b), Vertex(max_b)): (&Vertex, &Vertex)) -> bool {
+    (0..3).all(|axis| min_a[axis] <= max_b[axis] && min_b[axis] <= max_a[axis])
+}
+
+/**
+ * Whether two triangles cut through each other, with Möller's interval
+ * test: each triangle crosses the line where the two planes meet in an
+ * interval, and the triangles intersect if the intervals overlap by more
+ * than a point.
+ */
+fn triangles_cross(first: &[Vertex; 3], second: &[Vertex; 3]) -> bool {
+    let first_normal = triangle_normal(first);
+    let second_normal = triangle_normal(second);
+    if first_normal.length() == 0.0 || second_normal.length() == 0.0 {
+        return false;
+    }
+
+    let first_distances = plane_distances(first, &second_normal, &second[0]);
+    let second_distances = plane_distances(second, &first_normal, &first[0]);
+    // A triangle that only reaches the other's plane without crossing it
+    // can touch the other triangle, but not cut through it
+    if !straddles(&first_distances) || !straddles(&second_distances) {
+        return false;
+    }
+
+    // Project onto the axis closest to the line the planes meet on
+    let axis = longest_axis(&first_normal.cross(&second_normal));
+    let project = |triangle: &[Vertex; 3]| triangle.map(|Vertex(v)| v[axis]);
+
+    let (a_min, a_max) = line_interval(project(first), first_distances);
+    let (b_min, b_max) = line_interval(project(second), second_distances);
+    a_min.max(b_min) + TOUCHING < a_max.min(b_max)
+}
+
+/// The axis along which the vector has the largest component
+fn longest_axis(Vertex(vector): &Vertex) -> usize {
+    (0..3)
+        .max_by(|a, b| vector[*a].abs().total_cmp(&vector[*b].abs()))
+        .unwrap()
+}
+
+fn triangle_normal([a, b, c]: &[Vertex; 3]) -> Vertex {
+    b.sub(a).cross(&c.sub(a)).normalize()
+}
+
+/// Signed distances of the corners to a plane, snapped to 0 near it
+fn plane_distances(triangle: &[Vertex; 3], normal: &Vertex, point: &Vertex) -> [f32; 3] {
+    triangle.map(|corner| {
+        let distance = normal.dot(&corner.sub(point));
+        if distance.abs() < TOUCHING { 0.0 } else { distance }
+    })
+}
+
+fn straddles(distances: &[f32; 3]) -> bool {
+    distances.iter().any(|d| *d > 0.0) && distances.iter().any(|d| *d < 0.0)
+}
+
+/**
+ * Where a triangle that straddles the other triangle's plane crosses it,
+ * as an interval of the projected line. The corner alone on its side of
+ * the plane is connected to the other two.
+ */
+fn line_interval(projected: [f32; 3], distances: [f32; 3]) -> (f32, f32) {
+    // If two corners are on the same side, the third is alone. Otherwise
+    // one corner is on the plane and either of the others will do
+    let [d0, d1, d2] = distances;
+    let alone = if d0 * d1 > 0.0 {
+        2
+    } else if d0 * d2 > 0.0 {
+        1
+    } else if d0 != 0.0 {
+        0
+    } else {
+        1
+    };
+
+    let crossing = |other: usize| {
+        let t = distances[alone] / (distances[alone] - distances[other]);
+        projected[alone] + (projected[other] - projected[alone]) * t
+    };
+    let a = crossing((alone + 1) % 3);
+    let b = crossing((alone + 2) % 3);
+    (a.min(b), a.max(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::Face;
+    use crate::test_meshes::{cube, unwelded_cube};
+
+    /// A triangle in the z = 0 plane, and one given by its corners
+    fn pair(second: [[f32; 3]; 3]) -> Mesh {
+        let mut vertices = vec![
+            Vertex([0.0, 0.0, 0.0]),
+            Vertex([2.0, 0.0, 0.0]),
+            Vertex([0.0, 2.0, 0.0]),
+        ];
+        vertices.extend(second.iter().map(|corner| Vertex(*corner)));
+        let faces = vec![Face::Triangle([0, 1, 2]), Face::Triangle([3, 4, 5])];
+        Mesh::from_geometry(vertices, faces)
+    }
+
+    #[test]
+    fn crossing_triangles_are_reported() {
+        let mesh = pair([[0.5, 0.5, -1.0], [0.5, 0.5, 1.0], [1.5, -1.0, 0.0]]);
+        assert_eq!(mesh.self_intersections(), vec![Intersection {
+            faces: [0, 1],
+            cells: [None, None],
+        }]);
+    }
+
+    #[test]
+    fn triangles_sharing_an_edge_are_not_reported() {
+        let mesh = pair([[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.5, 0.5, 1.0]]);
+        assert!(mesh.self_intersections().is_empty());
+    }
+
+    #[test]
+    fn touching_triangles_are_not_reported() {
+        let mesh = pair([[0.5, 0.5, 0.0], [1.0, 0.5, 1.0], [0.5, 1.0, 1.0]]);
+        assert!(mesh.self_intersections().is_empty());
+    }
+
+    #[test]
+    fn overlapping_coplanar_triangles_are_not_reported() {
+        let mesh = pair([[0.5, 0.5, 0.0], [3.0, 0.5, 0.0], [0.5, 3.0, 0.0]]);
+        assert!(mesh.self_intersections().is_empty());
+    }
+
+    #[test]
+    fn closed_meshes_do_not_intersect_themselves() {
+        assert!(cube().self_intersections().is_empty());
+        assert!(unwelded_cube().self_intersections().is_empty());
+    }
+
+    #[test]
+    fn overlapping_cubes_intersect() {
+        let mut cubes = cube();
+        cubes.add_geometry(&cube().translate(&[0.5, 0.5, 0.5]));
+        assert!(!cubes.self_intersections().is_empty());
+    }
+}
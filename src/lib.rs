@@ -0,0 +1,52 @@
+extern crate rand;
+
+pub mod error;
+pub mod primitives;
+pub mod mesh;
+pub mod obj;
+pub mod stl;
+pub mod ply;
+pub mod gltf;
+pub mod threemf;
+mod zip;
+pub mod weld;
+pub mod normals;
+pub mod triangulate;
+pub mod clip;
+pub mod analysis;
+pub mod orientation;
+pub mod measure;
+pub mod transform;
+pub mod subdivide;
+pub mod smooth;
+pub mod decimate;
+pub mod components;
+pub mod remesh;
+pub mod intersection;
+pub mod cleanup;
+pub mod color;
+mod union_find;
+#[cfg(test)]
+mod test_meshes;
+pub mod parts;
+pub mod format;
+pub mod cube_rotations;
+pub mod tileset;
+pub mod grid;
+
+pub use error::MeshError;
+pub use format::{Encoding, FileFormat, SaveOptions};
+pub use mesh::{Mesh, FaceAttributes, Grouping};
+pub use ply::PlyAttributes;
+pub use parts::Parts;
+pub use analysis::MeshReport;
+pub use intersection::Intersection;
+pub use cleanup::CleanupReport;
+pub use triangulate::Triangulation;
+pub use subdivide::Subdivision;
+pub use smooth::SmoothOptions;
+pub use remesh::{Contouring, RemeshOptions};
+pub use cube_rotations::CubeRotation;
+pub use transform::Transform;
+pub use tileset::Tileset;
+pub use grid::{CellID, CellKind, Bounds, MirrorPolicy, KnotGridBuilder, GridError};
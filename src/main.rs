@@ -1,373 +1,331 @@
-extern crate rand;
-
-mod primitives;
-mod mesh;
-mod cube_rotations;
+mod cli;
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+use celtic_knots_3d::{
+    CellID, KnotGridBuilder, Mesh, MeshError, MirrorPolicy, SaveOptions, Tileset
+};
+use celtic_knots_3d::primitives::Vertex;
+use cli::{Command, GenerateArgs, CheckArgs, MeasureArgs, ColorBy, Normals};
+
+/// Build the grid and apply everything up to naming and coloring it
+fn build_grid(args: &GenerateArgs) -> Result<Mesh, MeshError> {
+    let [n, m, p] = args.dimensions;
+    let mirrors = if args.enable_mirrors {
+        MirrorPolicy::Random(args.mirror_chance)
+    } else {
+        MirrorPolicy::Disabled
+    };
 
-use rand::Rng;
+    let mut builder = KnotGridBuilder::new(Tileset::load(&args.tileset)?)
+        .dimensions(n, m, p)
+        .mirrors(mirrors);
+    if let Some(seed) = args.seed {
+        builder = builder.seed(seed);
+    }
+    if let Some(tolerance) = args.weld_tolerance {
+        builder = builder.weld(tolerance);
+    }
 
-use mesh::Mesh;
-use cube_rotations::CubeRotation;
+    let mut grid = clean_up(builder.build()?, args);
+    if args.orient {
+        grid = grid.orient_outward();
+    }
+    if let Some(options) = args.remesh.as_ref() {
+        grid = grid.remesh(options);
+    }
+    grid = grid.subdivide(args.subdivision, args.subdivision_levels);
+    if args.smoothing.iterations > 0 {
+        grid = grid.smooth(&args.smoothing);
+    }
+    if let Some(face_count) = args.decimate {
+        grid = grid.decimate(face_count);
+    }
+    // Smoothing pins the grid's bounds along each axis, so transform after
+    if let Some(transform) = args.transform.as_ref() {
+        grid = grid.transform(transform);
+    }
+    grid = match args.normals {
+        Some(Normals::Flat) => grid.with_flat_normals(),
+        Some(Normals::Smooth) => grid.with_smooth_normals(args.crease_angle),
+        Some(Normals::None) => grid.without_normals(),
+        None => grid
+    };
+    for (point, normal) in args.clip_planes.iter() {
+        grid = if args.cap {
+            grid.clip_plane_capped(*point, *normal)
+        } else {
+            grid.clip_plane(*point, *normal)
+        };
+    }
+    if let Some(method) = args.triangulation {
+        grid = grid.triangulate(method);
+    }
 
-fn format_path(tileset_name: &str, obj_name: &str) -> String {
-    format!("data/{}/{}.obj", tileset_name, obj_name)
+    Ok(grid)
 }
 
-fn make_quad_twist(tileset: &str) -> Mesh {
-    let twist = Mesh::from_obj_file(&format_path(tileset, "one_twist"));
-
-    let rz = CubeRotation::rz();
-    let twist2 = twist.rotate(&rz);
-
-    let twist3 = twist2.rotate(&rz);
-    let twist4 = twist3.rotate(&rz);
-
-    let mut result = Mesh::new();
-    result.add_geometry(&twist);
-    result.add_geometry(&twist2);
-    result.add_geometry(&twist3);
-    result.add_geometry(&twist4);
+/// Apply --cleanup, printing what it removed
+fn clean_up(mesh: Mesh, args: &GenerateArgs) -> Mesh {
+    if !args.cleanup {
+        return mesh;
+    }
 
-    result
+    let (mesh, report) = mesh.cleanup();
+    if !report.is_empty() {
+        println!("cleanup: {}", report);
+    }
+    mesh
 }
 
-fn make_connector(tileset: &str) -> Mesh {
-    let corner = Mesh::from_obj_file(&format_path(tileset, "one_corner"));
-    let rx = CubeRotation::rx();
-    let rx2 = &rx * &rx;
-    let rz = CubeRotation::rz();
-    let rz2 = &rz * &rz;
+fn generate(args: &GenerateArgs) -> Result<(), MeshError> {
+    let mut grid = build_grid(args)?;
 
-    let mut result = Mesh::new();
-    result.add_geometry(&corner);
-
-    let one_rotated = corner.rotate(&rx);
-    result.add_geometry(&one_rotated);
-
-    let two_rotated = result.rotate(&rx2);
-    result.add_geometry(&two_rotated);
-
-    let four_rotated = result.rotate(&rz2);
-    result.add_geometry(&four_rotated);
+    let mut options = SaveOptions {
+        cell_size: args.cell_size,
+        ..SaveOptions::default()
+    };
 
-    result
-}
+    if let Some(color_by) = args.color_by {
+        let parts = match color_by {
+            ColorBy::Strand => grid.parts_by_strand(),
+            ColorBy::Layer => grid.parts_by_layer(),
+            ColorBy::Kind => grid.parts_by_kind(),
+        }.with_palette(&args.palette);
+        grid.assign_materials(&parts);
+
+        // PLY can also record which strand each face is on
+        if color_by == ColorBy::Strand {
+            let strands = parts.face_parts.iter().map(|strand| *strand as i32).collect();
+            options.face_properties.push((String::from("strand"), strands));
+        }
 
-fn make_end_cap(tileset: &str) -> Mesh {
-    Mesh::from_obj_file(&format_path(tileset, "end_cap"))
-}
+        // Formats with separate objects get one per color
+        options.parts = Some(parts);
+    }
 
-fn make_edge_cap(tileset: &str) -> Mesh {
-    Mesh::from_obj_file(&format_path(tileset, "one_edge"))
-}
+    // Decimate the preview before naming objects by cell, since every
+    // object boundary would be a seam it has to keep
+    let mut preview = args.preview.as_ref()
+        .map(|_| grid.decimate(args.preview_faces));
 
-fn make_untwist(tileset: &str) -> Mesh {
-    let mut result = Mesh::from_obj_file(&format_path(tileset, "one_edge"));
+    if let Some(grouping) = args.grouping {
+        grid.name_by_provenance(grouping);
+        if let Some(preview) = preview.as_mut() {
+            preview.name_by_provenance(grouping);
+        }
+    }
 
-    let rz = CubeRotation::rz();
-    let rz2 = CubeRotation::rz2();
+    // Parts are per face, so they don't carry over to the preview or the
+    // split strands, but the materials do
+    let split_options = SaveOptions {
+        parts: None,
+        ..options.clone()
+    };
 
-    let rotated1 = result.rotate(&rz);
-    result.add_geometry(&rotated1);
+    if args.split {
+        for (i, component) in grid.connected_components().iter().enumerate() {
+            let path = numbered_path(&args.output, i);
+            component.save(&path, args.format, &split_options)?;
+        }
+    } else {
+        grid.save(&args.output, args.format, &options)?;
+    }
 
-    let rotated2 = result.rotate(&rz2);
-    result.add_geometry(&rotated2);
+    if let (Some((path, format)), Some(preview)) = (args.preview.as_ref(), preview) {
+        preview.save(path, *format, &split_options)?;
+    }
 
-    result
+    Ok(())
 }
 
-#[derive(Copy, Clone, Debug)]
-struct CellID(u32, u32, u32);
+/// grid.obj becomes grid_3.obj
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension)
+            => format!("{}_{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, number)
+    };
 
-#[derive(Debug)]
-enum RangeComparison {
-    Min,
-    Between,
-    Max
+    path.with_file_name(name)
 }
 
-#[derive(Debug)]
-struct BoundsClassification(
-    RangeComparison, 
-    RangeComparison,
-    RangeComparison
-);
-
-#[derive(Copy, Clone)]
-struct Range(u32, u32);
-
-#[derive(Copy, Clone)]
-struct Bounds(Range, Range, Range);
-
-impl Bounds {
-    pub fn new(n: u32, m: u32, p: u32) -> Self {
-        Bounds(
-            Range(0, n - 1),
-            Range(0, m - 1),
-            Range(0, p - 1)
-        )
+/**
+ * The meshes to check or measure: the given OBJ files, welded and cleaned
+ * up if asked for, or a generated grid if there are none
+ */
+fn load_meshes(files: &[PathBuf], args: &GenerateArgs)
+        -> Result<Vec<(String, Mesh)>, MeshError> {
+    let mut meshes: Vec<(String, Mesh)> = Vec::new();
+    if files.is_empty() {
+        meshes.push((String::from("generated grid"), build_grid(args)?));
     }
-}
-
-fn compare_range(x: u32, range: Range) -> RangeComparison {
-    let Range(min_val, max_val) = range;
-    use RangeComparison::{Min, Max, Between};
-    if x == min_val {
-        Min
-    } else if x == max_val {
-        Max
-    } else {
-        Between
+    for file in files.iter() {
+        let mut mesh = Mesh::from_obj_file(file)?;
+        if let Some(tolerance) = args.weld_tolerance {
+            mesh = mesh.weld(tolerance);
+        }
+        mesh = clean_up(mesh, args);
+        meshes.push((file.display().to_string(), mesh));
     }
-}
 
-fn classify_bounds(cell_id: CellID, bounds: Bounds) -> BoundsClassification {
-    let CellID(i, j, k) = cell_id;
-    let Bounds(x_range, y_range, z_range) = bounds;
-    BoundsClassification(
-        compare_range(i, x_range),
-        compare_range(j, y_range),
-        compare_range(k, z_range),
-    )
+    Ok(meshes)
 }
 
-fn twist_rotation(k: u32) -> CubeRotation {
-    let layer_parity = k % 2;
-    if layer_parity == 0 {
-        CubeRotation::rx()
-    } else {
-        CubeRotation::identity()
+/// How many intersecting faces check lists for each mesh
+const MAX_LISTED: usize = 10;
+
+/// Print a report for each mesh. Returns whether they were all clean
+fn check(args: &CheckArgs) -> Result<bool, MeshError> {
+    let mut all_clean = true;
+    let meshes = load_meshes(&args.files, &args.generate)?;
+    for (i, (name, mesh)) in meshes.iter().enumerate() {
+        let report = mesh.analyze();
+        all_clean &= report.is_clean();
+        if i > 0 {
+            println!();
+        }
+        println!("{}: {} vertices, {} faces", name,
+            mesh.vertices().len(), mesh.faces().len());
+        println!("{}", report);
+
+        if args.intersections {
+            let intersections = mesh.self_intersections();
+            all_clean &= intersections.is_empty();
+            println!("self-intersections:      {}", intersections.len());
+            for intersection in intersections.iter().take(MAX_LISTED) {
+                let [first, second] = intersection.faces;
+                let [first_cell, second_cell] = intersection.cells;
+                println!("  {} and {}", describe_face(first, first_cell),
+                    describe_face(second, second_cell));
+            }
+            if intersections.len() > MAX_LISTED {
+                println!("  ...");
+            }
+        }
     }
-}
-
-fn orient_twist_cell(
-        tileset: &str, cell_id: CellID, enable_mirrors: bool) -> Mesh {
-    let mut rng = rand::thread_rng();
 
-    let twist_tile = if enable_mirrors && rng.gen::<u8>() < 200 {
-        make_untwist(tileset)
-    } else {
-        make_quad_twist(tileset)
-    };
-
-
-    let CellID(i, j, k) = cell_id;
-    let rotation = twist_rotation(k);
-    let transformed = twist_tile
-        .rotate(&rotation)
-        .translate(&[i as f32, j as f32, k as f32]);
-
-    transformed
+    Ok(all_clean)
 }
 
-fn generate_end_cap(tileset: &str, cell_id: CellID, rotation: CubeRotation) -> Mesh {
-    let CellID(i, j, k) = cell_id;
-    return make_end_cap(tileset)
-        .rotate(&rotation)
-        .translate(&[i as f32, j as f32, k as f32])
-}
-
-fn generate_edge_cap(tileset: &str, cell_id: CellID, rotation: CubeRotation) -> Mesh {
-    let CellID(i, j, k) = cell_id;
-    return make_edge_cap(tileset)
-        .rotate(&rotation)
-        .translate(&[i as f32, j as f32, k as f32])
-}
-
-fn generate_twist_cell(
-        tileset: &str, cell_id: CellID, bounds: Bounds, enable_mirrors: bool) 
-        -> Mesh {
-    let classification = classify_bounds(cell_id, bounds);
-    use RangeComparison::{Min, Max, Between};
-    match classification {
-        BoundsClassification(Min, Between, Between) 
-            => generate_end_cap(tileset, cell_id, CubeRotation::ry3()),
-        BoundsClassification(Max, Between, Between)
-            => generate_end_cap(tileset, cell_id, CubeRotation::ry()),
-        BoundsClassification(Between, Min, Between)
-            => generate_end_cap(tileset, cell_id, CubeRotation::rx()),
-        BoundsClassification(Between, Max, Between)
-            => generate_end_cap(tileset, cell_id, CubeRotation::rx3()),
-        BoundsClassification(Between, Between, Min)
-            => generate_end_cap(tileset, cell_id, CubeRotation::ry2()),
-        BoundsClassification(Between, Between, Max)
-            => generate_end_cap(tileset, cell_id, CubeRotation::identity()),
-        BoundsClassification(Min, Min, Between)
-            => generate_edge_cap(tileset, cell_id, CubeRotation::identity()),
-        BoundsClassification(Min, Max, Between)
-            => generate_edge_cap(tileset, cell_id, CubeRotation::rz3()),
-        BoundsClassification(Max, Min, Between)
-            => generate_edge_cap(tileset, cell_id, CubeRotation::rz()),
-        BoundsClassification(Max, Max, Between)
-            => generate_edge_cap(tileset, cell_id, CubeRotation::rz2()),
-        _ => orient_twist_cell(tileset, cell_id, enable_mirrors)
+/// A face and the grid cell it was generated for, if any
+fn describe_face(face: usize, cell: Option<CellID>) -> String {
+    match cell {
+        Some(CellID(i, j, k)) => format!("face {} in cell ({}, {}, {})", face, i, j, k),
+        None => format!("face {}", face)
     }
 }
 
-fn generate_connector(tileset: &str, rotation: CubeRotation) -> Mesh {
-    make_connector(tileset)
-        .rotate(&rotation)
-}
-
-fn generate_connector_cell(
-        tileset: &str,
-        cell_id: CellID, 
-        rotation: CubeRotation, 
-        bounds: Bounds) -> Mesh {
-
-    let connector = generate_connector(tileset, rotation);
-
-    let classification = classify_bounds(cell_id, bounds);
-    use RangeComparison::{Min, Max, Between};
-    let clipped_connector = match classification {
-        BoundsClassification(Min, Between, Between) 
-            => connector.simple_clip([-1.0, 0.0, 0.0]),
-        BoundsClassification(Max, Between, Between)
-            => connector.simple_clip([1.0, 0.0, 0.0]),
-        BoundsClassification(Between, Min, Between)
-            => connector.simple_clip([0.0, -1.0, 0.0]),
-        BoundsClassification(Between, Max, Between)
-            => connector.simple_clip([0.0, 1.0, 0.0]),
-        BoundsClassification(Between, Between, Min)
-            => connector.simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Between, Between, Max)
-            => connector.simple_clip([0.0, 0.0, 1.0]),
-        // 12 edges
-        BoundsClassification(Min, Between, Min) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Min, Between, Max) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Max, Between, Min) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Max, Between, Max) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Between, Min, Min) 
-            => connector
-                .simple_clip([0.0, -1.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Between, Min, Max) 
-            => connector
-                .simple_clip([0.0, -1.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Between, Max, Min) 
-            => connector
-                .simple_clip([0.0, 1.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Between, Max, Max) 
-            => connector
-                .simple_clip([0.0, 1.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Min, Min, Between) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, -1.0, 0.0]),
-        BoundsClassification(Min, Max, Between) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, 1.0, 0.0]),
-        BoundsClassification(Max, Min, Between) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, -1.0, 0.0]),
-        BoundsClassification(Max, Max, Between) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, 1.0, 0.0]),
-        // 8 corners
-        BoundsClassification(Min, Min, Min) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, -1.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Min, Min, Max) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, -1.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Min, Max, Min) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, 1.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Min, Max, Max) 
-            => connector
-                .simple_clip([-1.0, 0.0, 0.0])
-                .simple_clip([0.0, 1.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Max, Min, Min) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, -1.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Max, Min, Max) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, -1.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        BoundsClassification(Max, Max, Min) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, 1.0, 0.0])
-                .simple_clip([0.0, 0.0, -1.0]),
-        BoundsClassification(Max, Max, Max) 
-            => connector
-                .simple_clip([1.0, 0.0, 0.0])
-                .simple_clip([0.0, 1.0, 0.0])
-                .simple_clip([0.0, 0.0, 1.0]),
-        _ => connector
-    };
-        
+/// Print the measurements of each mesh in millimeters
+fn measure(args: &MeasureArgs) -> Result<(), MeshError> {
+    let scale = args.generate.cell_size;
+    let meshes = load_meshes(&args.files, &args.generate)?;
+    for (i, (name, mesh)) in meshes.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}:", name);
 
-    let CellID(i, j, k) = cell_id;
-    clipped_connector.translate(&[i as f32, j as f32, k as f32]) 
-}
+        let (min, max) = match mesh.bounding_box() {
+            Some(bounds) => bounds,
+            None => {
+                println!("  empty mesh");
+                continue;
+            }
+        };
+        let Vertex([width, depth, height]) = max.sub(&min).scale(scale);
+        println!("  size:          {:.2} x {:.2} x {:.2} mm", width, depth, height);
+        println!("  surface area:  {:.2} mm^2",
+            mesh.surface_area() * scale * scale);
+
+        let volume = mesh.volume() * scale * scale * scale;
+        println!("  volume:        {:.2} mm^3", volume);
+        if let Some(centroid) = mesh.centroid() {
+            let Vertex([x, y, z]) = centroid.scale(scale);
+            println!("  centroid:      ({:.2}, {:.2}, {:.2}) mm", x, y, z);
+        }
 
-fn generate_cell(
-        tileset: &str, cell_id: CellID, bounds: Bounds, enable_mirrors: bool) 
-        -> Mesh {
-    let CellID(i, j, k) = cell_id;
-    let parities = (i % 2, j % 2, k % 2);
-
-    match parities {
-        (1, 1, 0) | (0, 0, 1) 
-            => generate_twist_cell(tileset, cell_id, bounds, enable_mirrors),
-        (1, 0, 1) | (0, 1, 0) 
-            => generate_connector_cell(
-                tileset, cell_id, CubeRotation::identity(), bounds),
-        (0, 0, 0) | (1, 1, 1)
-            => generate_connector_cell(tileset, cell_id, CubeRotation::ry(), bounds),
-        (1, 0, 0) | (0, 1, 1) 
-            => generate_connector_cell(tileset, cell_id, CubeRotation::rz(), bounds),
-        _ => panic!("Invalid cell parity")
+        // 1 cm^3 is 1000 mm^3
+        let grams = volume / 1000.0 * args.density;
+        println!("  filament:      {:.2} g at {} g/cm^3", grams, args.density);
+        if !mesh.analyze().is_watertight() {
+            let advice = if args.generate.weld_tolerance.is_none() {
+                "Try --weld"
+            } else {
+                "Run check to see where it is open"
+            };
+            println!("  warning: the surface is not closed, so the volume is \
+                not meaningful. {}", advice);
+        }
     }
+
+    Ok(())
 }
 
 fn main() {
-    const N: u32 = 5;
-    const M: u32 = 5;
-    const P: u32 = 5;
-    const TILESET: &str = "sturdy";
-    const ENABLE_MIRRORS: bool = false;
-    let bounds = Bounds::new(N, M, P);
-
-    let mut grid = Mesh::new();
-    for i in 0..N {
-        for j in 0..M {
-            for k in 0..P {
-                let cell_id = CellID(i, j, k);
-                let mesh = generate_cell(TILESET, cell_id, bounds, ENABLE_MIRRORS);
-                grid.add_geometry(&mesh);
+    let args = std::env::args().skip(1);
+    match cli::parse_args(args) {
+        Ok(Command::Generate(generate_args)) => {
+            if let Err(error) = generate(&generate_args) {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+        },
+        Ok(Command::Check(check_args)) => match check(&check_args) {
+            Ok(true) => {},
+            Ok(false) => process::exit(1),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                process::exit(1);
             }
+        },
+        Ok(Command::Measure(measure_args)) => {
+            if let Err(error) = measure(&measure_args) {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+        },
+        Ok(Command::Help) => println!("{}", cli::USAGE),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            eprintln!("Run 'celtic-knots-3d help' for usage.");
+            process::exit(2);
         }
     }
+}
 
-    grid.save_obj_file("grid.obj");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use celtic_knots_3d::Transform;
+
+    #[test]
+    fn rotating_after_smoothing_keeps_the_tile_boundary() {
+        let mut args = GenerateArgs {
+            dimensions: [2, 2, 1],
+            tileset: PathBuf::from("data/sturdy"),
+            seed: Some(3),
+            weld_tolerance: Some(1e-4),
+            ..GenerateArgs::default()
+        };
+        args.smoothing.iterations = 5;
+        // Only the grid's bounds hold the tile boundary in place
+        args.smoothing.pin_boundary = false;
+        let smoothed = build_grid(&args).unwrap();
+
+        let rotation = Transform::rotation_z(0.5);
+        args.transform = Some(rotation);
+        let rotated = build_grid(&args).unwrap();
+
+        assert_eq!(rotated.vertices().len(), smoothed.vertices().len());
+        for (actual, vertex) in rotated.vertices().iter().zip(smoothed.vertices()) {
+            let Vertex(expected) = rotation.apply_point(vertex);
+            let Vertex(actual) = actual;
+            assert!((0..3).all(|i| (actual[i] - expected[i]).abs() < 1e-4));
+        }
+    }
 }
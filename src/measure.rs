@@ -0,0 +1,131 @@
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+
+impl Mesh {
+    /// The corners of the axis-aligned box around all vertices, if any
+    pub fn bounding_box(&self) -> Option<(Vertex, Vertex)> {
+        if self.vertices.is_empty() {
+            None
+        } else {
+            Some(bounding_box(&self.vertices))
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        (0..self.faces.len()).map(|f| self.face_area(f)).sum()
+    }
+
+    /**
+     * The volume enclosed by the surface, as a sum of signed tetrahedra
+     * from the origin to each triangle. This is only meaningful for a
+     * closed mesh with outward facing normals, see analyze() and
+     * orient_outward().
+     */
+    pub fn volume(&self) -> f32 {
+        (0..self.faces.len()).map(|f| self.face_signed_volume(f)).sum()
+    }
+
+    /**
+     * The center of mass of the enclosed solid, assuming uniform density.
+     * If the mesh encloses no volume (e.g. it is a flat patch), this is the
+     * center of its surface instead.
+     */
+    pub fn centroid(&self) -> Option<Vertex> {
+        let mut weighted_sum = Vertex([0.0, 0.0, 0.0]);
+        let mut volume = 0.0;
+        for face in self.faces.iter() {
+            for [a, b, c] in face.fan_triangles() {
+                let a = &self.vertices[a];
+                let b = &self.vertices[b];
+                let c = &self.vertices[c];
+
+                // The tetrahedron with the origin has its centroid at
+                // (a + b + c) / 4
+                let tetrahedron = a.dot(&b.cross(c)) / 6.0;
+                weighted_sum = weighted_sum.add(
+                    &a.add(b).add(c).scale(tetrahedron / 4.0));
+                volume += tetrahedron;
+            }
+        }
+
+        if volume.abs() > f32::EPSILON {
+            return Some(weighted_sum.scale(1.0 / volume));
+        }
+
+        let mut weighted_sum = Vertex([0.0, 0.0, 0.0]);
+        let mut area = 0.0;
+        for face in self.faces.iter() {
+            for [a, b, c] in face.fan_triangles() {
+                let a = &self.vertices[a];
+                let b = &self.vertices[b];
+                let c = &self.vertices[c];
+                let triangle = 0.5 * b.sub(a).cross(&c.sub(a)).length();
+                weighted_sum = weighted_sum.add(
+                    &a.add(b).add(c).scale(triangle / 3.0));
+                area += triangle;
+            }
+        }
+
+        if area > 0.0 {
+            Some(weighted_sum.scale(1.0 / area))
+        } else {
+            None
+        }
+    }
+}
+
+/// The minimum and maximum corners of the box around the points
+pub(crate) fn bounding_box(points: &[Vertex]) -> (Vertex, Vertex) {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for Vertex(components) in points.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(components[axis]);
+            max[axis] = max[axis].max(components[axis]);
+        }
+    }
+
+    (Vertex(min), Vertex(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    fn assert_close(actual: &Vertex, expected: &Vertex) {
+        assert!(actual.sub(expected).length() < 1e-5,
+            "expected {:?}, found {:?}", expected, actual);
+    }
+
+    #[test]
+    fn cube_measurements() {
+        let mesh = cube().translate(&[1.0, 2.0, 3.0]);
+        let (min, max) = mesh.bounding_box().unwrap();
+        assert_eq!(min, Vertex([1.0, 2.0, 3.0]));
+        assert_eq!(max, Vertex([2.0, 3.0, 4.0]));
+        assert!((mesh.surface_area() - 6.0).abs() < 1e-5);
+
+        // Away from the origin, so the tetrahedra don't all have the same sign
+        assert!((mesh.volume() - 1.0).abs() < 1e-5);
+        assert_close(&mesh.centroid().unwrap(), &Vertex([1.5, 2.5, 3.5]));
+    }
+
+    #[test]
+    fn flat_patch_has_its_surface_center_as_centroid() {
+        let mut mesh = cube();
+        mesh.faces.truncate(1);
+        mesh.face_attributes.truncate(1);
+
+        assert_eq!(mesh.volume(), 0.0);
+        assert_close(&mesh.centroid().unwrap(), &Vertex([0.0, 0.5, 0.5]));
+    }
+
+    #[test]
+    fn empty_mesh_has_no_bounds_or_centroid() {
+        let mesh = Mesh::new();
+        assert_eq!(mesh.bounding_box(), None);
+        assert_eq!(mesh.centroid(), None);
+        assert_eq!(mesh.volume(), 0.0);
+    }
+}
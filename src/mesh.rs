@@ -1,104 +1,247 @@
-use std::io::{BufRead, BufReader, Write};
-use std::fs::File;
 use std::collections::{HashMap};
 
 use crate::primitives::{Vertex, Face};
-use Face::{Triangle, Quad};
 use crate::cube_rotations::CubeRotation;
+use crate::grid::{CellID, CellKind};
+use crate::parts::Parts;
+use crate::color::Color;
+
+/**
+ * Extra information about a face beyond its vertex indices. uvs and normals
+ * have one index per corner of the face, in the same order as the vertices.
+ * object, group and material are indices into the mesh's name tables.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaceAttributes {
+    pub uvs: Option<Face>,
+    pub normals: Option<Face>,
+    pub object: Option<usize>,
+    pub group: Option<usize>,
+    pub material: Option<usize>,
+    /// Smoothing group. 0 means smoothing is off
+    pub smoothing: u32,
+    /// The grid cell this face was generated for
+    pub cell: Option<CellID>,
+    pub kind: Option<CellKind>,
+}
+
+impl FaceAttributes {
+    /// Shift the uv and normal indices, used when concatenating meshes
+    fn offset(&self, uv_offset: usize, normal_offset: usize) -> Self {
+        Self {
+            uvs: self.uvs.as_ref().map(|f| f.offset(uv_offset)),
+            normals: self.normals.as_ref().map(|f| f.offset(normal_offset)),
+            ..self.clone()
+        }
+    }
+}
 
+#[derive(Clone)]
 pub struct Mesh {
-    vertices: Vec<Vertex>,
-    faces: Vec<Face>,
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) faces: Vec<Face>,
+    /// One entry per face
+    pub(crate) face_attributes: Vec<FaceAttributes>,
+    pub(crate) uvs: Vec<[f32; 2]>,
+    pub(crate) normals: Vec<Vertex>,
+    pub(crate) objects: Vec<String>,
+    pub(crate) groups: Vec<String>,
+    pub(crate) materials: Vec<String>,
+    pub(crate) material_libraries: Vec<String>,
+    /// Diffuse colors for materials, written to a companion .mtl file
+    pub(crate) material_colors: HashMap<String, Color>,
+}
+
+impl Default for Mesh {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Mesh {
     pub fn new() -> Self {
         Self {
             vertices: Vec::new(),
-            faces: Vec::new()
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            objects: Vec::new(),
+            groups: Vec::new(),
+            materials: Vec::new(),
+            material_libraries: Vec::new(),
+            material_colors: HashMap::new(),
         }
     }
 
-    pub fn from_obj_file(fname: &str) -> Self {
-        let mut file = File::open(fname).expect("Couldn't open file");
-        let (vertices, faces) = Mesh::parse_obj(&mut file);
-        
+    /**
+     * Make a mesh from bare geometry. Face indices must be valid indices
+     * into vertices.
+     */
+    pub fn from_geometry(vertices: Vec<Vertex>, faces: Vec<Face>) -> Self {
+        let face_attributes = vec![FaceAttributes::default(); faces.len()];
         Self {
-            vertices, 
-            faces
+            vertices,
+            faces,
+            face_attributes,
+            ..Self::new()
         }
     }
 
-    fn parse_obj(file: &mut File) -> (Vec<Vertex>, Vec<Face>) {
-        let reader = BufReader::new(file);
-        let mut vertices: Vec<Vertex> = Vec::new();
-        let mut faces: Vec<Face> = Vec::new();
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
 
-        for line in reader.lines() { 
-            let line_str = line.expect("could not read OBJ file line");
-            let tokens: Vec<&str> = line_str.split(" ").collect();
-            let tag = tokens[0];
+    pub fn faces(&self) -> &[Face] {
+        &self.faces
+    }
 
-            if tag == "v" {
-                let vertex = Mesh::parse_vertex(&tokens[1..]);
-                vertices.push(vertex);
-            } else if tag == "f" {
-                let face = Mesh::parse_face(&tokens[1..]);
-                faces.push(face);
-            } else {
-                // TODO: Support normals someday. But not today.
-            }
-        }
+    pub fn face_attributes(&self) -> &[FaceAttributes] {
+        &self.face_attributes
+    }
 
-        (vertices, faces)
+    pub fn uvs(&self) -> &[[f32; 2]] {
+        &self.uvs
     }
 
-    fn parse_vertex(tokens: &[&str]) -> Vertex {
-        if tokens.len() != 3 {
-            panic!("vertices must have 3 components");
-        }
+    pub fn normals(&self) -> &[Vertex] {
+        &self.normals
+    }
+
+    pub fn objects(&self) -> &[String] {
+        &self.objects
+    }
+
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn materials(&self) -> &[String] {
+        &self.materials
+    }
 
-        let x: f32 = tokens[0].parse().expect("invalid x coordinate");
-        let y: f32 = tokens[1].parse().expect("invalid x coordinate");
-        let z: f32 = tokens[2].parse().expect("invalid x coordinate");
+    pub fn material_libraries(&self) -> &[String] {
+        &self.material_libraries
+    }
 
-        Vertex([x, y, z])
+    pub fn material_color(&self, material: &str) -> Option<Color> {
+        self.material_colors.get(material).copied()
     }
 
-    fn parse_face(tokens: &[&str]) -> Face {
-        if tokens.len() < 3 || tokens.len() > 4 {
-            panic!("only triagles and quads are supported")
+    /**
+     * Give each part its own material, named after the part. If the parts
+     * have colors, those become the materials' diffuse colors.
+     */
+    pub fn assign_materials(&mut self, parts: &Parts) {
+        let materials: Vec<usize> = parts.names.iter()
+            .map(|name| intern_name(&mut self.materials, name.clone()))
+            .collect();
+
+        let assignments = self.face_attributes.iter_mut()
+            .zip(parts.face_parts.iter());
+        for (attributes, part) in assignments {
+            attributes.material = Some(materials[*part]);
+        }
+
+        if let Some(colors) = &parts.colors {
+            for (name, color) in parts.names.iter().zip(colors.iter()) {
+                self.material_colors.insert(name.clone(), *color);
+            }
         }
+    }
 
-        let v1 = Mesh::parse_face_index(&tokens[0]);
-        let v2 = Mesh::parse_face_index(&tokens[1]);
-        let v3 = Mesh::parse_face_index(&tokens[2]);
+    /**
+     * Unit normal of a triangle given by vertex indices, pointing towards
+     * the side where the triangle winds counterclockwise. Degenerate
+     * triangles have a zero normal.
+     */
+    pub fn triangle_normal(&self, triangle: [usize; 3]) -> Vertex {
+        let [a, b, c] = triangle;
+        let ab = self.vertices[b].sub(&self.vertices[a]);
+        let ac = self.vertices[c].sub(&self.vertices[a]);
+        ab.cross(&ac).normalize()
+    }
 
-        if tokens.len() == 3 {
-            return Face::Triangle([v1, v2, v3])
+    /**
+     * Unit normal of a face using Newell's method, which also gives a
+     * sensible answer for nonplanar quads and polygons
+     */
+    pub fn face_normal(&self, face_index: usize) -> Vertex {
+        let indices = self.faces[face_index].indices();
+        let mut normal = Vertex([0.0, 0.0, 0.0]);
+        for (i, current) in indices.iter().enumerate() {
+            let next = indices[(i + 1) % indices.len()];
+            let a = self.vertices[*current];
+            let b = self.vertices[next];
+            normal = normal.add(&a.cross(&b));
         }
 
-        let v4 = Mesh::parse_face_index(tokens[3]);
-        Face::Quad([v1, v2, v3, v4])
+        normal.normalize()
     }
 
-    fn parse_face_index(index_str: &str) -> usize {
-        let indices: Vec<&str> = index_str.split("/").collect(); 
-        let vertex: usize = indices[0].parse().expect("invalid face index");
-        // TODO: maybe someday uvs and normals?
+    /// Record which grid cell every face of this mesh belongs to
+    pub fn with_provenance(mut self, cell: CellID, kind: CellKind) -> Self {
+        for attributes in self.face_attributes.iter_mut() {
+            attributes.cell = Some(cell);
+            attributes.kind = Some(kind);
+        }
+        self
+    }
 
-        // 1-indexed values -> 0-indexed values
-        vertex - 1
+    /**
+     * Name OBJ objects and groups after where each face came from. Grouping
+     * by cell gives each cell its own object (e.g. twist_3_1_2) with a group
+     * for its kind. Grouping by kind puts all cells of the same kind in one
+     * object. Faces without provenance keep their names.
+     */
+    pub fn name_by_provenance(&mut self, grouping: Grouping) {
+        // Big grids have many thousands of cells, so cache the name lookups
+        let mut objects: HashMap<(CellID, CellKind), usize> = HashMap::new();
+        let mut groups: HashMap<CellKind, usize> = HashMap::new();
+
+        for attributes in self.face_attributes.iter_mut() {
+            let (cell, kind) = match attributes {
+                FaceAttributes { cell: Some(cell), kind: Some(kind), .. }
+                    => (*cell, *kind),
+                _ => continue
+            };
+
+            let object_names = &mut self.objects;
+            let object = *objects.entry((cell, kind)).or_insert_with(|| {
+                let name = match grouping {
+                    Grouping::Cell => cell_name(cell, kind),
+                    Grouping::Kind => kind.name().to_string(),
+                };
+                intern_name(object_names, name)
+            });
+
+            let group_names = &mut self.groups;
+            let group = *groups.entry(kind).or_insert_with(|| {
+                intern_name(group_names, kind.name().to_string())
+            });
+
+            attributes.object = Some(object);
+            attributes.group = Some(group);
+        }
+    }
+
+    pub(crate) fn push_face(&mut self, face: Face, attributes: FaceAttributes) {
+        self.faces.push(face);
+        self.face_attributes.push(attributes);
     }
 
     pub fn rotate(&self, rotation: &CubeRotation) -> Self {
         let rotated_vertices: Vec<Vertex> = self.vertices.iter().map(|v| {
             rotation * v
         }).collect();
+        let rotated_normals: Vec<Vertex> = self.normals.iter().map(|n| {
+            rotation * n
+        }).collect();
 
         Self {
             vertices: rotated_vertices,
-            faces: self.faces.clone()
+            normals: rotated_normals,
+            ..self.clone()
         }
     }
 
@@ -109,24 +252,39 @@ impl Mesh {
 
             Vertex([x + dx, y + dy, z + dz])
         }).collect();
-            
+
         Self {
             vertices: translated_vertices,
-            faces: self.faces.clone()
+            ..self.clone()
         }
     }
 
     pub fn add_geometry(&mut self, other: &Self) {
         let n = self.vertices.len();
-            
+        let uv_offset = self.uvs.len();
+        let normal_offset = self.normals.len();
+
         self.vertices.extend_from_slice(&other.vertices[..]);
-        
-        for face in other.faces.iter() {
-            let new_face = match face {
-                Quad([v1, v2, v3, v4]) => Quad([v1 + n, v2 + n, v3 + n, v4 + n]),
-                Triangle([v1, v2, v3]) => Triangle([v1 + n, v2 + n, v3 + n])
-            };
-            self.faces.push(new_face);
+        self.uvs.extend_from_slice(&other.uvs[..]);
+        self.normals.extend_from_slice(&other.normals[..]);
+
+        // The other mesh has its own name tables, so names must be looked
+        // up again in ours
+        let objects = merge_names(&mut self.objects, &other.objects);
+        let groups = merge_names(&mut self.groups, &other.groups);
+        let materials = merge_names(&mut self.materials, &other.materials);
+        merge_names(&mut self.material_libraries, &other.material_libraries);
+        for (name, color) in other.material_colors.iter() {
+            self.material_colors.entry(name.clone()).or_insert(*color);
+        }
+
+        let faces = other.faces.iter().zip(other.face_attributes.iter());
+        for (face, attributes) in faces {
+            let mut new_attributes = attributes.offset(uv_offset, normal_offset);
+            new_attributes.object = attributes.object.map(|i| objects[i]);
+            new_attributes.group = attributes.group.map(|i| groups[i]);
+            new_attributes.material = attributes.material.map(|i| materials[i]);
+            self.push_face(face.offset(n), new_attributes);
         }
     }
 
@@ -148,72 +306,216 @@ impl Mesh {
             // If we're "inside" the boundary, keep the vertex but give
             // it a new index
             if product <= 0.0 {
-                new_vertices.push(vertex.clone());
+                new_vertices.push(*vertex);
                 keepers.insert(i, new_vertices.len() - 1);
             }
         }
 
         // Go through the faces and see which ones to keep
-        let mut new_faces: Vec<Face> = Vec::new();
-        for face in self.faces.iter() {
-            let new_face = reindex_face(&face, &keepers);
+        let mut result = Self {
+            vertices: new_vertices,
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..self.clone()
+        };
+        let faces = self.faces.iter().zip(self.face_attributes.iter());
+        for (face, attributes) in faces {
+            let new_face = reindex_face(face, &keepers);
 
             if let Some(f) = new_face {
-                new_faces.push(f);
+                result.push_face(f, attributes.clone());
             }
         }
 
-        Self {
-            vertices: new_vertices,
-            faces: new_faces,
-        }
+        result
     }
+}
 
-    pub fn save_obj_file(&self, fname: &str) {
-        let mut file = File::create(fname)
-            .expect("Could not open output OBJ file");
+/**
+ * Drop corners of a face that repeat the previous corner's vertex (going
+ * around the face), along with their uv and normal indices. Returns None if
+ * fewer than 3 corners are left.
+ */
+pub(crate) fn remove_repeated_corners(
+        face: &Face, attributes: &FaceAttributes)
+        -> Option<(Face, FaceAttributes)> {
+    let indices = face.indices();
+    let n = indices.len();
+    let keep: Vec<usize> = (0..n)
+        .filter(|i| indices[*i] != indices[(i + n - 1) % n])
+        .collect();
+
+    if keep.len() == n {
+        return Some((face.clone(), attributes.clone()));
+    }
+    if keep.len() < 3 {
+        return None;
+    }
 
-        for Vertex([x, y, z]) in self.vertices.iter() {
-            let line = format!("v {} {} {}\n", x, y, z);
-            file.write_all(line.as_bytes()).expect("Could not write vertex");
-        }
+    let pick = |corners: &Face| {
+        let values = corners.indices();
+        Face::from_indices(keep.iter().map(|i| values[*i]).collect())
+    };
+    let new_attributes = FaceAttributes {
+        uvs: attributes.uvs.as_ref().map(pick),
+        normals: attributes.normals.as_ref().map(pick),
+        ..attributes.clone()
+    };
 
-        for face in self.faces.iter() {
-            let line = match face {
-                Quad([v1, v2, v3, v4]) => format!(
-                    "f {} {} {} {}\n", v1 + 1, v2 + 1, v3 + 1, v4 + 1),
-                Triangle([v1, v2, v3]) => format!(
-                    "f {} {} {}\n", v1 + 1, v2 + 1, v3 + 1)
-            };
-            file.write_all(line.as_bytes()).expect("Could not write face");
+    Some((pick(face), new_attributes))
+}
+
+/// How to name the parts of a generated grid
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Grouping {
+    Cell,
+    Kind,
+}
+
+/// A name like twist_3_1_2 for a cell
+pub fn cell_name(cell: CellID, kind: CellKind) -> String {
+    let CellID(i, j, k) = cell;
+    format!("{}_{}_{}_{}", kind.name(), i, j, k)
+}
+
+/// Find a name in the table, adding it if it's not there yet
+pub(crate) fn intern_name(names: &mut Vec<String>, name: String) -> usize {
+    match names.iter().position(|x| *x == name) {
+        Some(index) => index,
+        None => {
+            names.push(name);
+            names.len() - 1
         }
     }
 }
 
-fn reindex_face(face: &Face, keepers: &HashMap<usize, usize>) -> Option<Face> {
-    let accept = match face {
-        Triangle(vertices) => accept_face(&vertices[..], &keepers),
-        Quad(vertices) => accept_face(&vertices[..], &keepers),
-    };
+/**
+ * Add any names from other that are not already in names. Returns where each
+ * of the other names ended up.
+ */
+fn merge_names(names: &mut Vec<String>, other: &[String]) -> Vec<usize> {
+    other.iter().map(|name| intern_name(names, name.clone())).collect()
+}
 
-    if !accept {
+fn reindex_face(face: &Face, keepers: &HashMap<usize, usize>) -> Option<Face> {
+    if !accept_face(face.indices(), keepers) {
         return None;
     }
 
-    match face {
-        Triangle([v1, v2, v3]) 
-            => Some(Triangle([keepers[v1], keepers[v2], keepers[v3]])),
-        Quad([v1, v2, v3, v4]) 
-            => Some(Quad([keepers[v1], keepers[v2], keepers[v3], keepers[v4]])),
-    }
+    Some(face.map(|v| keepers[&v]))
 }
 
 fn accept_face(face_indices: &[usize], keepers: &HashMap<usize, usize>) -> bool {
     for index in face_indices.iter() {
-        if let None = keepers.get(index) {
+        if keepers.get(index).is_none() {
             return false;
         }
     }
 
-    return true;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    /// A twist cell and the connector next to it, as a cube each
+    fn two_cells() -> Mesh {
+        let mut mesh = cube().with_provenance(CellID(0, 1, 2), CellKind::Twist);
+        mesh.add_geometry(&cube()
+            .translate(&[1.0, 0.0, 0.0])
+            .with_provenance(CellID(1, 1, 2), CellKind::Connector));
+        mesh
+    }
+
+    /// The name of each face in the table
+    fn face_names<'a>(names: &'a [String], indices: &[Option<usize>]) -> Vec<&'a str> {
+        indices.iter()
+            .map(|index| index.map_or("none", |index| names[index].as_str()))
+            .collect()
+    }
+
+    fn objects(mesh: &Mesh) -> Vec<&str> {
+        let indices: Vec<Option<usize>> = mesh.face_attributes().iter()
+            .map(|attributes| attributes.object)
+            .collect();
+        face_names(mesh.objects(), &indices)
+    }
+
+    fn groups(mesh: &Mesh) -> Vec<&str> {
+        let indices: Vec<Option<usize>> = mesh.face_attributes().iter()
+            .map(|attributes| attributes.group)
+            .collect();
+        face_names(mesh.groups(), &indices)
+    }
+
+    #[test]
+    fn grouping_by_cell_gives_each_cell_an_object() {
+        let mut mesh = two_cells();
+        mesh.name_by_provenance(Grouping::Cell);
+
+        assert_eq!(mesh.objects(), &["twist_0_1_2", "connector_1_1_2"]);
+        assert_eq!(mesh.groups(), &["twist", "connector"]);
+        assert_eq!(objects(&mesh)[..6], ["twist_0_1_2"; 6]);
+        assert_eq!(objects(&mesh)[6..], ["connector_1_1_2"; 6]);
+        assert_eq!(groups(&mesh)[..6], ["twist"; 6]);
+        assert_eq!(groups(&mesh)[6..], ["connector"; 6]);
+    }
+
+    #[test]
+    fn grouping_by_kind_shares_objects_between_cells() {
+        let mut mesh = two_cells();
+        mesh.add_geometry(&cube()
+            .translate(&[2.0, 0.0, 0.0])
+            .with_provenance(CellID(2, 1, 2), CellKind::Twist));
+        mesh.name_by_provenance(Grouping::Kind);
+
+        assert_eq!(mesh.objects(), &["twist", "connector"]);
+        assert_eq!(objects(&mesh)[..6], ["twist"; 6]);
+        assert_eq!(objects(&mesh)[6..12], ["connector"; 6]);
+        assert_eq!(objects(&mesh)[12..], ["twist"; 6]);
+        assert_eq!(objects(&mesh), groups(&mesh));
+    }
+
+    #[test]
+    fn faces_without_provenance_keep_their_names() {
+        let mut mesh = cube();
+        mesh.objects = vec![String::from("frame")];
+        mesh.face_attributes[0].object = Some(0);
+        mesh.add_geometry(&cube().with_provenance(CellID(0, 0, 0), CellKind::EndCap));
+        mesh.name_by_provenance(Grouping::Cell);
+
+        assert_eq!(mesh.objects(), &["frame", "end_cap_0_0_0"]);
+        assert_eq!(objects(&mesh)[0], "frame");
+        assert_eq!(objects(&mesh)[1..6], ["none"; 5]);
+        assert_eq!(objects(&mesh)[6..], ["end_cap_0_0_0"; 6]);
+    }
+
+    #[test]
+    fn added_geometry_shares_names_already_in_the_table() {
+        let mut mesh = cube();
+        mesh.objects = vec![String::from("knot"), String::from("frame")];
+        mesh.materials = vec![String::from("red")];
+        mesh.face_attributes[0].object = Some(1);
+        mesh.face_attributes[0].material = Some(0);
+
+        let mut other = cube();
+        other.objects = vec![String::from("frame"), String::from("strand")];
+        other.materials = vec![String::from("blue"), String::from("red")];
+        other.face_attributes[0].object = Some(0);
+        other.face_attributes[0].material = Some(1);
+        other.face_attributes[1].object = Some(1);
+        other.face_attributes[1].material = Some(0);
+        mesh.add_geometry(&other);
+
+        // Names in both tables aren't repeated, and the added faces point
+        // at the same entries as the faces that were already there
+        assert_eq!(mesh.objects(), &["knot", "frame", "strand"]);
+        assert_eq!(mesh.materials(), &["red", "blue"]);
+        let added = &mesh.face_attributes()[6..8];
+        assert_eq!((added[0].object, added[0].material), (Some(1), Some(0)));
+        assert_eq!((added[1].object, added[1].material), (Some(2), Some(1)));
+        assert_eq!(added[0].object, mesh.face_attributes()[0].object);
+    }
 }
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::Mesh;
+
+/// Edges sharper than this many degrees stay sharp by default
+pub const DEFAULT_CREASE_ANGLE: f32 = 30.0;
+
+impl Mesh {
+    /// The flat normal of every face, see face_normal()
+    pub fn face_normals(&self) -> Vec<Vertex> {
+        (0..self.faces.len()).map(|i| self.face_normal(i)).collect()
+    }
+
+    /**
+     * One smooth normal per vertex, averaging the normals of the faces
+     * around it weighted by the angle each face makes at the vertex. Faces
+     * only count as neighbors if they share vertex indices, so weld the mesh
+     * first to smooth across tile seams.
+     */
+    pub fn vertex_normals(&self) -> Vec<Vertex> {
+        let face_normals = self.face_normals();
+        let mut sums = vec![Vertex([0.0, 0.0, 0.0]); self.vertices.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            for (corner, vertex) in face.indices().iter().enumerate() {
+                let angle = self.corner_angle(face, corner);
+                sums[*vertex] = sums[*vertex].add(&face_normals[f].scale(angle));
+            }
+        }
+
+        sums.iter().map(|sum| sum.normalize()).collect()
+    }
+
+    /**
+     * Replace any normals with one flat normal per face, for a faceted look
+     */
+    pub fn with_flat_normals(&self) -> Self {
+        let mut result = self.clone();
+        result.normals = self.face_normals();
+        for (f, face) in self.faces.iter().enumerate() {
+            result.face_attributes[f].normals = Some(face.map(|_| f));
+        }
+
+        result
+    }
+
+    /**
+     * Replace any normals with angle-weighted smooth normals. Where two
+     * faces meet at more than crease_angle degrees, the edge between them is
+     * kept sharp. Like vertex_normals(), this only smooths across faces that
+     * share vertex indices.
+     */
+    pub fn with_smooth_normals(&self, crease_angle: f32) -> Self {
+        let cos_crease = crease_angle.to_radians().cos();
+        let face_normals = self.face_normals();
+
+        // Faces around each vertex, along with the angle at that corner
+        let mut incident: Vec<Vec<(usize, f32)>> =
+            vec![Vec::new(); self.vertices.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            for (corner, vertex) in face.indices().iter().enumerate() {
+                incident[*vertex].push((f, self.corner_angle(face, corner)));
+            }
+        }
+
+        // Corners that end up with the same normal share an entry
+        let mut normals: Vec<Vertex> = Vec::new();
+        let mut lookup: HashMap<(usize, [i32; 3]), usize> = HashMap::new();
+        let mut corner_normals: Vec<Face> = Vec::with_capacity(self.faces.len());
+        for (f, face) in self.faces.iter().enumerate() {
+            let normal = &face_normals[f];
+            let mut corners = Vec::with_capacity(face.len());
+            for vertex in face.indices().iter().copied() {
+                let mut sum = Vertex([0.0, 0.0, 0.0]);
+                for (other, angle) in incident[vertex].iter() {
+                    let other_normal = &face_normals[*other];
+                    if normal.dot(other_normal) >= cos_crease {
+                        sum = sum.add(&other_normal.scale(*angle));
+                    }
+                }
+
+                let smooth = if sum.length() > 0.0 {
+                    sum.normalize()
+                } else {
+                    *normal
+                };
+
+                let Vertex([x, y, z]) = smooth;
+                let key = (vertex, [
+                    (x * 1e5).round() as i32,
+                    (y * 1e5).round() as i32,
+                    (z * 1e5).round() as i32
+                ]);
+                corners.push(*lookup.entry(key).or_insert_with(|| {
+                    normals.push(smooth);
+                    normals.len() - 1
+                }));
+            }
+            corner_normals.push(Face::from_indices(corners));
+        }
+
+        let mut result = self.clone();
+        result.normals = normals;
+        for (attributes, corners) in
+                result.face_attributes.iter_mut().zip(corner_normals) {
+            attributes.normals = Some(corners);
+        }
+
+        result
+    }
+
+    /// Remove all normals
+    pub fn without_normals(&self) -> Self {
+        let mut result = self.clone();
+        result.normals.clear();
+        for attributes in result.face_attributes.iter_mut() {
+            attributes.normals = None;
+        }
+
+        result
+    }
+
+    pub fn has_normals(&self) -> bool {
+        self.face_attributes.iter().any(|attributes| attributes.normals.is_some())
+    }
+
+    /// The interior angle of a face at one of its corners, in radians
+    fn corner_angle(&self, face: &Face, corner: usize) -> f32 {
+        let indices = face.indices();
+        let n = indices.len();
+        let current = &self.vertices[indices[corner]];
+        let previous = self.vertices[indices[(corner + n - 1) % n]].sub(current);
+        let next = self.vertices[indices[(corner + 1) % n]].sub(current);
+
+        let lengths = previous.length() * next.length();
+        if lengths == 0.0 {
+            return 0.0;
+        }
+
+        (previous.dot(&next) / lengths).clamp(-1.0, 1.0).acos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    fn assert_close(actual: &Vertex, expected: &Vertex) {
+        assert!(actual.sub(expected).length() < 1e-5,
+            "expected {:?}, found {:?}", expected, actual);
+    }
+
+    /// The normal at each corner of a face
+    fn corner_normals(mesh: &Mesh, face: usize) -> Vec<Vertex> {
+        let corners = mesh.face_attributes()[face].normals.as_ref().unwrap();
+        corners.indices().iter().map(|n| mesh.normals()[*n]).collect()
+    }
+
+    #[test]
+    fn flat_normals_are_the_face_normals() {
+        let mesh = cube().with_flat_normals();
+        assert_eq!(mesh.normals().len(), 6);
+        for f in 0..mesh.faces().len() {
+            for normal in corner_normals(&mesh, f) {
+                assert_close(&normal, &mesh.face_normal(f));
+            }
+        }
+        assert_close(&mesh.face_normal(0), &Vertex([-1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn edges_sharper_than_the_crease_angle_stay_sharp() {
+        // The edges of a cube are at 90 degrees
+        let mesh = cube().with_smooth_normals(30.0);
+        assert_eq!(mesh.normals().len(), 24);
+        for f in 0..mesh.faces().len() {
+            for normal in corner_normals(&mesh, f) {
+                assert_close(&normal, &mesh.face_normal(f));
+            }
+        }
+    }
+
+    #[test]
+    fn edges_within_the_crease_angle_are_smoothed() {
+        let mesh = cube().with_smooth_normals(100.0);
+        assert_eq!(mesh.normals().len(), 8);
+
+        // Corner 0 of the x = 0 face is the origin
+        let diagonal = Vertex([-1.0, -1.0, -1.0]).normalize();
+        assert_close(&corner_normals(&mesh, 0)[0], &diagonal);
+        assert_close(&cube().vertex_normals()[0], &diagonal);
+    }
+
+    #[test]
+    fn normals_can_be_removed() {
+        let mesh = cube().with_flat_normals().without_normals();
+        assert!(!mesh.has_normals());
+        assert!(mesh.normals().is_empty());
+    }
+}
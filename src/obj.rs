@@ -0,0 +1,626 @@
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::{Mesh, FaceAttributes, intern_name};
+use crate::error::MeshError;
+use crate::color::linear_to_srgb;
+
+/// The name written for faces outside any object, group or material. It is
+/// read back as no name at all.
+const DEFAULT_NAME: &str = "default";
+
+/**
+ * The attribute state that the OBJ format sets with one statement and then
+ * applies to every following face
+ */
+#[derive(Default)]
+struct ParseState {
+    object: Option<usize>,
+    group: Option<usize>,
+    material: Option<usize>,
+    smoothing: u32,
+}
+
+impl Mesh {
+    /**
+     * Read a mesh from an OBJ file. Any line that can't be parsed is reported
+     * as an error along with the file name and line number.
+     */
+    pub fn from_obj_file<P: AsRef<Path>>(fname: P) -> Result<Self, MeshError> {
+        let fname = fname.as_ref().display().to_string();
+        let file = File::open(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        Mesh::from_obj_reader(BufReader::new(file), &fname)
+    }
+
+    /**
+     * Read a mesh in OBJ format from any reader. fname is only used for
+     * error messages.
+     *
+     * This handles the geometry subset of OBJ: v, vt, vn, f (all index
+     * forms, including negative relative indices), o, g, usemtl, mtllib
+     * and s. Other statements like curves and lines are skipped.
+     */
+    pub fn from_obj_reader<R: BufRead>(reader: R, fname: &str)
+            -> Result<Self, MeshError> {
+        let mut mesh = Mesh::new();
+        let mut state = ParseState::default();
+
+        let mut lines = reader.lines().enumerate();
+        while let Some((i, line)) = lines.next() {
+            let line_number = i + 1;
+            let mut line_str = line.map_err(|error| MeshError::io(fname, error))?;
+
+            // A backslash at the end of a line joins it with the next one
+            while line_str.ends_with('\\') {
+                line_str.pop();
+                line_str.push(' ');
+                match lines.next() {
+                    Some((_, next)) => line_str.push_str(
+                        &next.map_err(|error| MeshError::io(fname, error))?),
+                    None => break
+                }
+            }
+
+            mesh.parse_statement(&line_str, &mut state)
+                .map_err(|reason| MeshError::parse(
+                    fname, line_number, reason))?;
+        }
+
+        Ok(mesh)
+    }
+
+    fn parse_statement(&mut self, line: &str, state: &mut ParseState)
+            -> Result<(), String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (tag, args) = match tokens.split_first() {
+            Some((tag, args)) => (*tag, args),
+            None => return Ok(())
+        };
+
+        match tag {
+            "v" => {
+                let vertex = parse_vertex(args)?;
+                self.vertices.push(vertex);
+            },
+            "vt" => {
+                let uv = parse_uv(args)?;
+                self.uvs.push(uv);
+            },
+            "vn" => {
+                let normal = parse_normal(args)?;
+                self.normals.push(normal);
+            },
+            "f" => {
+                let (face, attributes) = self.parse_face(args, state)?;
+                self.push_face(face, attributes);
+            },
+            "o" => {
+                state.object = intern_state(&mut self.objects, args);
+            },
+            "g" => {
+                state.group = intern_state(&mut self.groups, args);
+            },
+            "usemtl" => {
+                state.material = intern_state(&mut self.materials, args);
+            },
+            "mtllib" => {
+                intern(&mut self.material_libraries, args);
+            },
+            "s" => {
+                state.smoothing = parse_smoothing(args)?;
+            },
+            // Comments, and statements that don't describe polygon
+            // geometry (points, lines, curves, etc.)
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn parse_face(&self, tokens: &[&str], state: &ParseState)
+            -> Result<(Face, FaceAttributes), String> {
+        if tokens.len() < 3 {
+            return Err(format!(
+                "faces must have at least 3 vertices, found {}", tokens.len()));
+        }
+
+        let mut vertices: Vec<usize> = Vec::new();
+        let mut uvs: Vec<usize> = Vec::new();
+        let mut normals: Vec<usize> = Vec::new();
+        for token in tokens.iter() {
+            let (vertex, uv, normal) = self.parse_corner(token)?;
+            vertices.push(vertex);
+            uvs.extend(uv);
+            normals.extend(normal);
+        }
+
+        // uvs and normals are only kept if every corner has one
+        let n = vertices.len();
+        let attributes = FaceAttributes {
+            uvs: if uvs.len() == n { Some(Face::from_indices(uvs)) } else { None },
+            normals: if normals.len() == n {
+                Some(Face::from_indices(normals))
+            } else {
+                None
+            },
+            object: state.object,
+            group: state.group,
+            material: state.material,
+            smoothing: state.smoothing,
+            ..FaceAttributes::default()
+        };
+
+        Ok((Face::from_indices(vertices), attributes))
+    }
+
+    /**
+     * Parse one corner of a face in any of the forms v, v/vt, v//vn or
+     * v/vt/vn
+     */
+    fn parse_corner(&self, token: &str)
+            -> Result<(usize, Option<usize>, Option<usize>), String> {
+        let parts: Vec<&str> = token.split('/').collect();
+        if parts.len() > 3 {
+            return Err(format!("invalid face vertex '{}'", token));
+        }
+
+        let vertex = parse_index(parts[0], self.vertices.len(), "vertex")?;
+        let uv = match parts.get(1) {
+            Some(part) if !part.is_empty()
+                => Some(parse_index(part, self.uvs.len(), "texture coordinate")?),
+            _ => None
+        };
+        let normal = match parts.get(2) {
+            Some(part) if !part.is_empty()
+                => Some(parse_index(part, self.normals.len(), "normal")?),
+            _ => None
+        };
+
+        Ok((vertex, uv, normal))
+    }
+
+    /**
+     * Save the mesh as an OBJ file. If any materials have colors, they are
+     * written to a .mtl file with the same name next to the OBJ file.
+     */
+    pub fn save_obj_file<P: AsRef<Path>>(&self, fname: P)
+            -> Result<(), MeshError> {
+        let path = fname.as_ref();
+        let fname = path.display().to_string();
+
+        let mut material_library: Option<String> = None;
+        if !self.material_colors.is_empty() {
+            let mtl_path = path.with_extension("mtl");
+            let mtl_fname = mtl_path.display().to_string();
+            let mtl_file = File::create(&mtl_path)
+                .map_err(|error| MeshError::io(&mtl_fname, error))?;
+            self.write_mtl(BufWriter::new(mtl_file))
+                .map_err(|error| MeshError::io(&mtl_fname, error))?;
+
+            // The OBJ refers to the library relative to itself
+            material_library = mtl_path.file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+        }
+
+        let file = File::create(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        self.write_obj_with_library(
+                BufWriter::new(file), material_library.as_deref())
+            .map_err(|error| MeshError::io(&fname, error))
+    }
+
+    pub fn write_obj<W: Write>(&self, file: W) -> io::Result<()> {
+        self.write_obj_with_library(file, None)
+    }
+
+    /**
+     * Write a material library with a diffuse color for each material that
+     * has one. Viewers read Kd as the sRGB color shown on screen, so that's
+     * what is written rather than the linear color
+     */
+    pub fn write_mtl<W: Write>(&self, mut file: W) -> io::Result<()> {
+        for name in self.materials.iter() {
+            if let Some(color) = self.material_colors.get(name) {
+                let [r, g, b] = color.map(|c| linear_to_srgb(c.clamp(0.0, 1.0)));
+                writeln!(file, "newmtl {}", name)?;
+                writeln!(file, "Ka 0 0 0")?;
+                writeln!(file, "Kd {} {} {}", r, g, b)?;
+                writeln!(file, "Ks 0 0 0")?;
+                writeln!(file, "d 1")?;
+                writeln!(file, "illum 1")?;
+                writeln!(file)?;
+            }
+        }
+
+        file.flush()
+    }
+
+    fn write_obj_with_library<W: Write>(
+            &self, mut file: W, extra_library: Option<&str>)
+            -> io::Result<()> {
+        if let Some(library) = extra_library {
+            writeln!(file, "mtllib {}", library)?;
+        }
+        for library in self.material_libraries.iter() {
+            if Some(library.as_str()) != extra_library {
+                writeln!(file, "mtllib {}", library)?;
+            }
+        }
+
+        for Vertex([x, y, z]) in self.vertices.iter() {
+            writeln!(file, "v {} {} {}", x, y, z)?;
+        }
+
+        for [u, v] in self.uvs.iter() {
+            writeln!(file, "vt {} {}", u, v)?;
+        }
+
+        for Vertex([x, y, z]) in self.normals.iter() {
+            writeln!(file, "vn {} {} {}", x, y, z)?;
+        }
+
+        // Keep each object's faces together, otherwise importers may
+        // split it into several objects
+        let mut order: Vec<usize> = (0..self.faces.len()).collect();
+        order.sort_by_key(|i| self.face_attributes[*i].object);
+
+        let mut state = ParseState::default();
+        for i in order {
+            let attributes = &self.face_attributes[i];
+            self.write_state_changes(&mut file, &mut state, attributes)?;
+            write_face(&mut file, &self.faces[i], attributes)?;
+        }
+
+        file.flush()
+    }
+
+    /**
+     * Emit o/g/usemtl/s statements for any attributes that differ from the
+     * previous face. Going back to no object, group or material is written
+     * as the default name.
+     */
+    fn write_state_changes<W: Write>(
+            &self,
+            file: &mut W,
+            state: &mut ParseState,
+            attributes: &FaceAttributes) -> io::Result<()> {
+        if attributes.object != state.object {
+            writeln!(file, "o {}", name_or_default(&self.objects, attributes.object))?;
+            state.object = attributes.object;
+        }
+
+        if attributes.group != state.group {
+            writeln!(file, "g {}", name_or_default(&self.groups, attributes.group))?;
+            state.group = attributes.group;
+        }
+
+        if attributes.material != state.material {
+            let material = name_or_default(&self.materials, attributes.material);
+            writeln!(file, "usemtl {}", material)?;
+            state.material = attributes.material;
+        }
+
+        if attributes.smoothing != state.smoothing {
+            match attributes.smoothing {
+                0 => writeln!(file, "s off")?,
+                group => writeln!(file, "s {}", group)?
+            }
+            state.smoothing = attributes.smoothing;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_face<W: Write>(file: &mut W, face: &Face, attributes: &FaceAttributes)
+        -> io::Result<()> {
+    write!(file, "f")?;
+    for (i, v) in face.indices().iter().enumerate() {
+        let uv = attributes.uvs.as_ref().map(|uvs| uvs.indices()[i]);
+        let normal = attributes.normals.as_ref().map(|n| n.indices()[i]);
+
+        // OBJ indices are 1-indexed
+        match (uv, normal) {
+            (None, None) => write!(file, " {}", v + 1)?,
+            (Some(vt), None) => write!(file, " {}/{}", v + 1, vt + 1)?,
+            (None, Some(vn)) => write!(file, " {}//{}", v + 1, vn + 1)?,
+            (Some(vt), Some(vn))
+                => write!(file, " {}/{}/{}", v + 1, vt + 1, vn + 1)?,
+        }
+    }
+    writeln!(file)
+}
+
+/**
+ * Names may contain spaces (g can even list several groups), so keep the
+ * whole rest of the line
+ */
+fn intern(names: &mut Vec<String>, tokens: &[&str]) -> usize {
+    intern_name(names, tokens.join(" "))
+}
+
+/// Like intern, but the default name (or no name) means none
+fn intern_state(names: &mut Vec<String>, tokens: &[&str]) -> Option<usize> {
+    match tokens {
+        [] | [DEFAULT_NAME] => None,
+        _ => Some(intern(names, tokens))
+    }
+}
+
+fn name_or_default(names: &[String], index: Option<usize>) -> &str {
+    index.map_or(DEFAULT_NAME, |index| &names[index])
+}
+
+fn parse_float(token: &str, label: &str) -> Result<f32, String> {
+    token.parse().map_err(|_| format!("invalid {} '{}'", label, token))
+}
+
+/// Vertices may have an optional w component or RGB color, which are ignored
+fn parse_vertex(tokens: &[&str]) -> Result<Vertex, String> {
+    if tokens.len() < 3 || tokens.len() > 7 {
+        return Err(format!(
+            "vertices must have 3 components, found {}", tokens.len()));
+    }
+
+    let x = parse_float(tokens[0], "x coordinate")?;
+    let y = parse_float(tokens[1], "y coordinate")?;
+    let z = parse_float(tokens[2], "z coordinate")?;
+    for token in tokens[3..].iter() {
+        parse_float(token, "vertex component")?;
+    }
+
+    Ok(Vertex([x, y, z]))
+}
+
+/// The v and w components of a texture coordinate are optional
+fn parse_uv(tokens: &[&str]) -> Result<[f32; 2], String> {
+    if tokens.is_empty() || tokens.len() > 3 {
+        return Err(format!(
+            "texture coordinates must have 1 to 3 components, found {}",
+            tokens.len()));
+    }
+
+    let u = parse_float(tokens[0], "u coordinate")?;
+    let v = match tokens.get(1) {
+        Some(token) => parse_float(token, "v coordinate")?,
+        None => 0.0
+    };
+    if let Some(token) = tokens.get(2) {
+        parse_float(token, "w coordinate")?;
+    }
+
+    Ok([u, v])
+}
+
+fn parse_normal(tokens: &[&str]) -> Result<Vertex, String> {
+    if tokens.len() != 3 {
+        return Err(format!(
+            "normals must have 3 components, found {}", tokens.len()));
+    }
+
+    let x = parse_float(tokens[0], "normal x component")?;
+    let y = parse_float(tokens[1], "normal y component")?;
+    let z = parse_float(tokens[2], "normal z component")?;
+
+    Ok(Vertex([x, y, z]))
+}
+
+fn parse_smoothing(tokens: &[&str]) -> Result<u32, String> {
+    match tokens {
+        ["off"] => Ok(0),
+        [group] => group.parse().map_err(|_| format!(
+            "invalid smoothing group '{}'", group)),
+        _ => Err(String::from("expected a single smoothing group"))
+    }
+}
+
+/**
+ * Convert an OBJ index to a 0-indexed one. Positive indices count from 1,
+ * negative ones count backwards from the most recent element. count is how
+ * many elements have been defined so far.
+ */
+fn parse_index(token: &str, count: usize, label: &str) -> Result<usize, String> {
+    let index: i64 = token.parse().map_err(|_| format!(
+        "invalid {} index '{}'", label, token))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range, only {} defined",
+            label, index, count));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::parse_hex_color;
+    use crate::test_meshes::cube;
+
+    fn parse(text: &str) -> Result<Mesh, MeshError> {
+        Mesh::from_obj_reader(text.as_bytes(), "test.obj")
+    }
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vn 0 0 1
+";
+
+    #[test]
+    fn every_corner_form_is_read() {
+        let faces = "f 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf 1/1/1 2/2/1 3/3/1\n";
+        let text = format!("{}{}", SQUARE, faces);
+        let mesh = parse(&text).unwrap();
+        let attributes = mesh.face_attributes();
+        assert!(mesh.faces().iter().all(|face| *face == Face::Triangle([0, 1, 2])));
+
+        let uvs = attributes.iter().map(|a| a.uvs.clone()).collect::<Vec<_>>();
+        let normals = attributes.iter().map(|a| a.normals.clone()).collect::<Vec<_>>();
+        let corners = Some(Face::Triangle([0, 1, 2]));
+        let normal = Some(Face::Triangle([0, 0, 0]));
+        assert_eq!(uvs, vec![None, corners.clone(), None, corners]);
+        assert_eq!(normals, vec![None, None, normal.clone(), normal]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest() {
+        let mesh = parse(&format!("{}f -4/-3/-1 -3/-2/-1 -2/-1/-1\n", SQUARE)).unwrap();
+        assert_eq!(mesh.faces(), &[Face::Triangle([0, 1, 2])]);
+        assert_eq!(mesh.face_attributes()[0].uvs, Some(Face::Triangle([0, 1, 2])));
+    }
+
+    #[test]
+    fn corners_are_only_kept_if_every_corner_has_them() {
+        let mesh = parse(&format!("{}f 1/1 2 3/3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.face_attributes()[0].uvs, None);
+    }
+
+    #[test]
+    fn faces_keep_their_corner_count() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\n";
+        let text = format!("{}f 1 2 3 4\nf 1 2 3 4 5\n", vertices);
+        let mesh = parse(&text).unwrap();
+        assert_eq!(mesh.faces(), &[
+            Face::Quad([0, 1, 2, 3]),
+            Face::Polygon(vec![0, 1, 2, 3, 4]),
+        ]);
+    }
+
+    #[test]
+    fn names_apply_to_the_faces_that_follow() {
+        let statements = "o knot\nf 1 2 3\ng strand 1\nusemtl red\ns 2\nf 1 3 4\n";
+        let text = format!("{}{}", SQUARE, statements);
+        let mesh = parse(&text).unwrap();
+        assert_eq!(mesh.objects(), &["knot"]);
+        assert_eq!(mesh.groups(), &["strand 1"]);
+        assert_eq!(mesh.materials(), &["red"]);
+
+        let [first, second] = [&mesh.face_attributes()[0], &mesh.face_attributes()[1]];
+        assert_eq!((first.object, first.group, first.material, first.smoothing),
+            (Some(0), None, None, 0));
+        assert_eq!((second.object, second.group, second.material, second.smoothing),
+            (Some(0), Some(0), Some(0), 2));
+    }
+
+    #[test]
+    fn backslashes_continue_lines() {
+        let mesh = parse(&format!("{}f 1 \\\n2 3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.faces(), &[Face::Triangle([0, 1, 2])]);
+    }
+
+    #[test]
+    fn errors_give_the_line_number() {
+        let message = |text: &str| parse(text).err().unwrap().to_string();
+        assert_eq!(message("v 0 0 0\n\nf 1 2 3\n"),
+            "test.obj:3: vertex index 2 is out of range, only 1 defined");
+        assert_eq!(message(&format!("{}f 0 1 2\n", SQUARE)),
+            "test.obj:9: vertex index 0 is out of range, only 4 defined");
+        assert_eq!(message("v 0 zero 0\n"), "test.obj:1: invalid y coordinate 'zero'");
+        assert_eq!(message(&format!("{}f 1 2\n", SQUARE)),
+            "test.obj:9: faces must have at least 3 vertices, found 2");
+        assert_eq!(message(&format!("{}f 1/1/1/1 2 3\n", SQUARE)),
+            "test.obj:9: invalid face vertex '1/1/1/1'");
+    }
+
+    #[test]
+    fn written_meshes_read_back_the_same() {
+        // The cube with one normal per face, like flat shading
+        let mut mesh = cube();
+        for (f, face) in cube().faces().iter().enumerate() {
+            let mut normal = [0.0; 3];
+            normal[f / 2] = if f % 2 == 0 { -1.0 } else { 1.0 };
+            mesh.normals.push(Vertex(normal));
+            mesh.face_attributes[f].normals = Some(face.map(|_| f));
+        }
+        let mut text: Vec<u8> = Vec::new();
+        mesh.write_obj(&mut text).unwrap();
+
+        let read = Mesh::from_obj_reader(&text[..], "cube.obj").unwrap();
+        let coordinates = |vertices: &[Vertex]| vertices.iter()
+            .map(|Vertex(coordinates)| *coordinates)
+            .collect::<Vec<_>>();
+        assert_eq!(coordinates(read.vertices()), coordinates(mesh.vertices()));
+        assert_eq!(read.faces(), mesh.faces());
+        assert_eq!(coordinates(read.normals()), coordinates(mesh.normals()));
+        assert_eq!(read.face_attributes(), mesh.face_attributes());
+    }
+
+    #[test]
+    fn leaving_an_object_group_or_material_reads_back_as_none() {
+        let mut mesh = cube();
+        mesh.objects = vec![String::from("knot")];
+        mesh.groups = vec![String::from("strand")];
+        mesh.materials = vec![String::from("red")];
+        for (f, attributes) in mesh.face_attributes.iter_mut().enumerate() {
+            attributes.object = if f < 3 { Some(0) } else { None };
+            attributes.group = if f % 2 == 0 { Some(0) } else { None };
+            attributes.material = if f == 2 || f == 5 { None } else { Some(0) };
+        }
+        let mut text: Vec<u8> = Vec::new();
+        mesh.write_obj(&mut text).unwrap();
+
+        let read = Mesh::from_obj_reader(&text[..], "cube.obj").unwrap();
+        assert_eq!(read.objects(), mesh.objects());
+        assert_eq!(read.groups(), mesh.groups());
+        assert_eq!(read.materials(), mesh.materials());
+        // The faces of each object are written together
+        let mut written = mesh.face_attributes().to_vec();
+        written.sort_by_key(|attributes| attributes.object);
+        assert_eq!(read.face_attributes(), &written[..]);
+    }
+
+    #[test]
+    fn default_names_are_no_names() {
+        let statements = "\
+o knot
+g strand
+usemtl red
+f 1 2 3
+o default
+g
+usemtl default
+f 1 3 4
+";
+        let mesh = parse(&format!("{}{}", SQUARE, statements)).unwrap();
+        let second = &mesh.face_attributes()[1];
+        assert_eq!((second.object, second.group, second.material), (None, None, None));
+    }
+
+    #[test]
+    fn material_colors_are_written_as_srgb() {
+        let mut mesh = cube();
+        mesh.materials.push(String::from("orange"));
+        let orange = parse_hex_color("ff8000").unwrap();
+        mesh.material_colors.insert(String::from("orange"), orange);
+        let mut text: Vec<u8> = Vec::new();
+        mesh.write_mtl(&mut text).unwrap();
+
+        let text = String::from_utf8(text).unwrap();
+        let diffuse: Vec<f32> = text.lines()
+            .find(|line| line.starts_with("Kd "))
+            .unwrap()
+            .split_whitespace()
+            .skip(1)
+            .map(|value| value.parse().unwrap())
+            .collect();
+        let expected = [1.0, 128.0 / 255.0, 0.0];
+        assert!(diffuse.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-5),
+            "Kd {:?}", diffuse);
+    }
+}
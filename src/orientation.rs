@@ -0,0 +1,179 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::primitives::Face;
+use crate::mesh::Mesh;
+
+impl Mesh {
+    /**
+     * Make the winding of every face consistent with its neighbors and
+     * point all normals outward. Starting from one face of each connected
+     * component, the orientation is spread across every edge shared by
+     * exactly two faces. Afterwards, components that enclose a negative
+     * volume are inside out, so all their faces are flipped.
+     *
+     * Connectivity is by vertex index, so weld the mesh first. Normals of
+     * flipped faces are negated too.
+     */
+    pub fn orient_outward(&self) -> Self {
+        let flips = self.orientation_flips();
+
+        let mut result = self.clone();
+        let mut negated_normals: HashMap<usize, usize> = HashMap::new();
+        for (f, flip) in flips.iter().enumerate() {
+            if !flip {
+                continue;
+            }
+
+            result.faces[f] = self.faces[f].reversed();
+            let attributes = &mut result.face_attributes[f];
+            let normals = &mut result.normals;
+            attributes.uvs = attributes.uvs.as_ref().map(Face::reversed);
+            if let Some(corners) = attributes.normals.as_ref() {
+                let mut negated: Vec<usize> = Vec::new();
+                for normal in corners.reversed().indices() {
+                    negated.push(*negated_normals.entry(*normal)
+                        .or_insert_with(|| {
+                            normals.push(self.normals[*normal].scale(-1.0));
+                            normals.len() - 1
+                        }));
+                }
+                attributes.normals = Some(Face::from_indices(negated));
+            }
+        }
+
+        result
+    }
+
+    /// Which faces need to be flipped to orient the mesh outward
+    fn orientation_flips(&self) -> Vec<bool> {
+        // The faces using each edge, keyed with the lower vertex first,
+        // along with whether they go from the lower vertex to the higher
+        let mut edges: HashMap<[usize; 2], Vec<(usize, bool)>> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let indices = face.indices();
+            for (i, a) in indices.iter().enumerate() {
+                let b = indices[(i + 1) % indices.len()];
+                if *a != b {
+                    edges.entry([*a.min(&b), *a.max(&b)])
+                        .or_default()
+                        .push((f, *a < b));
+                }
+            }
+        }
+
+        let mut flips = vec![false; self.faces.len()];
+        let mut visited = vec![false; self.faces.len()];
+        for start in 0..self.faces.len() {
+            if visited[start] {
+                continue;
+            }
+
+            // Breadth first search over the component. A neighbor has to
+            // go the other way along the shared edge
+            let mut component: Vec<usize> = Vec::new();
+            let mut queue = VecDeque::from(vec![start]);
+            visited[start] = true;
+            while let Some(f) = queue.pop_front() {
+                component.push(f);
+                let indices = self.faces[f].indices();
+                for (i, a) in indices.iter().enumerate() {
+                    let b = indices[(i + 1) % indices.len()];
+                    let uses = match edges.get(&[*a.min(&b), *a.max(&b)]) {
+                        Some(uses) if uses.len() == 2 => uses,
+                        _ => continue
+                    };
+
+                    let (g, g_forward) =
+                        if uses[0].0 == f { uses[1] } else { uses[0] };
+                    if visited[g] {
+                        continue;
+                    }
+
+                    // The edge direction as f ends up after flipping
+                    let f_direction = (*a < b) != flips[f];
+                    flips[g] = f_direction == g_forward;
+                    visited[g] = true;
+                    queue.push_back(g);
+                }
+            }
+
+            let volume: f32 = component.iter().map(|f| {
+                let volume = self.face_signed_volume(*f);
+                if flips[*f] { -volume } else { volume }
+            }).sum();
+            if volume < 0.0 {
+                for f in component {
+                    flips[f] = !flips[f];
+                }
+            }
+        }
+
+        flips
+    }
+
+    /**
+     * The signed volume of the cone from the origin to a face. Summed over
+     * a closed surface, this is the volume it encloses, positive if the
+     * faces are wound counterclockwise seen from outside.
+     */
+    pub(crate) fn face_signed_volume(&self, face_index: usize) -> f32 {
+        self.faces[face_index].fan_triangles().iter().map(|[a, b, c]| {
+            let a = &self.vertices[*a];
+            let b = &self.vertices[*b];
+            let c = &self.vertices[*c];
+            a.dot(&b.cross(c)) / 6.0
+        }).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::{cube, fan_volume};
+
+    fn inverted_cube() -> Mesh {
+        let mut mesh = cube();
+        mesh.faces = mesh.faces.iter().map(Face::reversed).collect();
+        mesh
+    }
+
+    #[test]
+    fn inverted_cube_is_turned_right_side_out() {
+        let mesh = inverted_cube();
+        assert!((fan_volume(&mesh) + 1.0).abs() < 1e-6);
+
+        let oriented = mesh.orient_outward();
+        assert!((fan_volume(&oriented) - 1.0).abs() < 1e-6);
+        assert!(oriented.analyze().is_clean());
+    }
+
+    #[test]
+    fn single_flipped_face_is_fixed() {
+        let mut mesh = cube();
+        mesh.faces[3] = mesh.faces[3].reversed();
+
+        let oriented = mesh.orient_outward();
+        assert_eq!(oriented.faces(), cube().faces());
+    }
+
+    #[test]
+    fn each_component_is_oriented_separately() {
+        let mut mesh = cube();
+        mesh.add_geometry(&inverted_cube().translate(&[2.0, 0.0, 0.0]));
+
+        let oriented = mesh.orient_outward();
+        assert!((fan_volume(&oriented) - 2.0).abs() < 1e-5);
+        assert!(oriented.analyze().is_clean());
+    }
+
+    #[test]
+    fn normals_of_flipped_faces_are_negated() {
+        let oriented = inverted_cube().with_flat_normals().orient_outward();
+        for (f, attributes) in oriented.face_attributes().iter().enumerate() {
+            let outward = oriented.face_normal(f);
+            for n in attributes.normals.as_ref().unwrap().indices() {
+                assert!(oriented.normals()[*n].dot(&outward) > 0.99);
+            }
+        }
+    }
+}
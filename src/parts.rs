@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use crate::primitives::Vertex;
+use crate::mesh::{Mesh, cell_name};
+use crate::color::Color;
+use crate::union_find::UnionFind;
+
+/**
+ * A grouping of a mesh's faces into named parts, such as strands or cell
+ * kinds. Exporters use this to split the output into separate objects or
+ * to assign materials.
+ */
+#[derive(Clone, Debug)]
+pub struct Parts {
+    pub names: Vec<String>,
+    /// Index into names for each face of the mesh
+    pub face_parts: Vec<usize>,
+    /// Optional color for each part
+    pub colors: Option<Vec<Color>>,
+}
+
+impl Parts {
+    /// Put every face in a single part
+    pub fn single(name: &str, face_count: usize) -> Self {
+        Self {
+            names: vec![name.to_string()],
+            face_parts: vec![0; face_count],
+            colors: None,
+        }
+    }
+
+    /**
+     * Group faces by a key. Parts are named by the key and ordered by
+     * first appearance.
+     */
+    pub fn from_keys<I>(keys: I) -> Self
+            where I: IntoIterator<Item = String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut lookup: HashMap<String, usize> = HashMap::new();
+        let mut face_parts: Vec<usize> = Vec::new();
+        for key in keys {
+            let part = *lookup.entry(key.clone()).or_insert_with(|| {
+                names.push(key);
+                names.len() - 1
+            });
+            face_parts.push(part);
+        }
+
+        Self {
+            names,
+            face_parts,
+            colors: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Assign a color to each part by cycling through a palette
+    pub fn with_palette(mut self, palette: &[Color]) -> Self {
+        if !palette.is_empty() {
+            self.colors = Some((0..self.len()).map(|i| {
+                palette[i % palette.len()]
+            }).collect());
+        }
+        self
+    }
+
+    /// The indices of the faces in each part
+    pub fn faces_per_part(&self) -> Vec<Vec<usize>> {
+        let mut result: Vec<Vec<usize>> = vec![Vec::new(); self.len()];
+        for (face, part) in self.face_parts.iter().enumerate() {
+            result[*part].push(face);
+        }
+
+        result
+    }
+}
+
+impl Mesh {
+    /// One part per OBJ object. Faces outside any object go in "default"
+    pub fn parts_by_object(&self) -> Parts {
+        Parts::from_keys(self.face_attributes.iter().map(|attributes| {
+            match attributes.object {
+                Some(object) => self.objects[object].clone(),
+                None => String::from("default")
+            }
+        }))
+    }
+
+    /// One part per grid cell. Faces with no provenance go in "default"
+    pub fn parts_by_cell(&self) -> Parts {
+        Parts::from_keys(self.face_attributes.iter().map(|attributes| {
+            match (attributes.cell, attributes.kind) {
+                (Some(cell), Some(kind)) => cell_name(cell, kind),
+                _ => String::from("default")
+            }
+        }))
+    }
+
+    /// One part per kind of cell. Faces with no provenance go in "default"
+    pub fn parts_by_kind(&self) -> Parts {
+        Parts::from_keys(self.face_attributes.iter().map(|attributes| {
+            match attributes.kind {
+                Some(kind) => kind.name().to_string(),
+                None => String::from("default")
+            }
+        }))
+    }
+
+    /// One part per layer k of the grid. Faces with no provenance go in "default"
+    pub fn parts_by_layer(&self) -> Parts {
+        Parts::from_keys(self.face_attributes.iter().map(|attributes| {
+            match attributes.cell {
+                Some(cell) => format!("layer_{}", cell.2),
+                None => String::from("default")
+            }
+        }))
+    }
+
+    /**
+     * One part per strand of the knot. Each tile is a separate piece of
+     * geometry, so faces are considered connected when they share a vertex
+     * position, not just a vertex index.
+     */
+    pub fn parts_by_strand(&self) -> Parts {
+        let positions = position_ids(&self.vertices);
+        let mut sets = UnionFind::new(self.vertices.len());
+        for (vertex, position) in positions.iter().enumerate() {
+            sets.union(*position, vertex);
+        }
+        for face in self.faces.iter() {
+            let indices = face.indices();
+            for vertex in indices[1..].iter() {
+                sets.union(indices[0], *vertex);
+            }
+        }
+
+        let mut strands: HashMap<usize, usize> = HashMap::new();
+        let face_parts: Vec<usize> = self.faces.iter().map(|face| {
+            let root = sets.find(face.indices()[0]);
+            let count = strands.len();
+            *strands.entry(root).or_insert(count)
+        }).collect();
+
+        Parts {
+            names: (0..strands.len()).map(|i| format!("strand_{}", i)).collect(),
+            face_parts,
+            colors: None,
+        }
+    }
+
+    /// One part per material. Faces with no material go in "default"
+    pub fn parts_by_material(&self) -> Parts {
+        Parts::from_keys(self.face_attributes.iter().map(|attributes| {
+            match attributes.material {
+                Some(material) => self.materials[material].clone(),
+                None => String::from("default")
+            }
+        }))
+    }
+}
+
+/**
+ * For each vertex, the index of the first vertex at the same position.
+ * Positions are rounded slightly so float error from rotations and
+ * translations doesn't keep tiles apart.
+ */
+pub(crate) fn position_ids(vertices: &[Vertex]) -> Vec<usize> {
+    const PRECISION: f32 = 1e4;
+    let mut first_seen: HashMap<[i64; 3], usize> = HashMap::new();
+    vertices.iter().enumerate().map(|(i, Vertex(components))| {
+        let mut key = [0i64; 3];
+        for (k, component) in key.iter_mut().zip(components.iter()) {
+            *k = (component * PRECISION).round() as i64;
+        }
+        *first_seen.entry(key).or_insert(i)
+    }).collect()
+}
@@ -0,0 +1,303 @@
+use std::io::{self, BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+use crate::error::MeshError;
+use crate::format::Encoding;
+
+/**
+ * Optional data to write alongside the positions and faces of a PLY file.
+ * Vertex attributes must have one entry per vertex, face properties one
+ * entry per face.
+ */
+#[derive(Default)]
+pub struct PlyAttributes<'a> {
+    /// RGB vertex colors
+    pub colors: Option<&'a [[u8; 3]]>,
+    pub normals: Option<&'a [Vertex]>,
+    /// Named integer properties like a cell or strand id
+    pub face_properties: Vec<(&'a str, &'a [i32])>,
+}
+
+impl Mesh {
+    pub fn save_ply<P: AsRef<Path>>(
+            &self,
+            fname: P,
+            encoding: Encoding,
+            attributes: &PlyAttributes) -> Result<(), MeshError> {
+        let fname = fname.as_ref().display().to_string();
+        let file = File::create(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        self.write_ply(BufWriter::new(file), encoding, attributes)
+            .map_err(|error| MeshError::io(&fname, error))
+    }
+
+    pub fn write_ply<W: Write>(
+            &self,
+            mut file: W,
+            encoding: Encoding,
+            attributes: &PlyAttributes) -> io::Result<()> {
+        self.check_ply_attributes(attributes)?;
+        self.write_ply_header(&mut file, encoding, attributes)?;
+
+        match encoding {
+            Encoding::Ascii => self.write_ply_ascii(&mut file, attributes)?,
+            Encoding::Binary => self.write_ply_binary(&mut file, attributes)?,
+        }
+
+        file.flush()
+    }
+
+    fn check_ply_attributes(&self, attributes: &PlyAttributes)
+            -> io::Result<()> {
+        let vertex_count = self.vertices.len();
+        let face_count = self.faces.len();
+
+        if let Some(colors) = attributes.colors {
+            check_length("vertex colors", colors.len(), vertex_count)?;
+        }
+
+        if let Some(normals) = attributes.normals {
+            check_length("vertex normals", normals.len(), vertex_count)?;
+        }
+
+        for (name, values) in attributes.face_properties.iter() {
+            check_length(name, values.len(), face_count)?;
+        }
+
+        // The vertex count of each face is stored as a uchar
+        if self.faces.iter().any(|face| face.len() > u8::MAX as usize) {
+            return Err(invalid_input(String::from(
+                "PLY faces can have at most 255 vertices")));
+        }
+
+        Ok(())
+    }
+
+    fn write_ply_header<W: Write>(
+            &self,
+            file: &mut W,
+            encoding: Encoding,
+            attributes: &PlyAttributes) -> io::Result<()> {
+        let format = match encoding {
+            Encoding::Ascii => "ascii",
+            Encoding::Binary => "binary_little_endian",
+        };
+
+        writeln!(file, "ply")?;
+        writeln!(file, "format {} 1.0", format)?;
+        writeln!(file, "comment generated by celtic-knots-3d")?;
+        writeln!(file, "element vertex {}", self.vertices.len())?;
+        writeln!(file, "property float x")?;
+        writeln!(file, "property float y")?;
+        writeln!(file, "property float z")?;
+        if attributes.normals.is_some() {
+            writeln!(file, "property float nx")?;
+            writeln!(file, "property float ny")?;
+            writeln!(file, "property float nz")?;
+        }
+        if attributes.colors.is_some() {
+            writeln!(file, "property uchar red")?;
+            writeln!(file, "property uchar green")?;
+            writeln!(file, "property uchar blue")?;
+        }
+        writeln!(file, "element face {}", self.faces.len())?;
+        writeln!(file, "property list uchar int vertex_indices")?;
+        for (name, _) in attributes.face_properties.iter() {
+            writeln!(file, "property int {}", name)?;
+        }
+        writeln!(file, "end_header")
+    }
+
+    fn write_ply_ascii<W: Write>(
+            &self, file: &mut W, attributes: &PlyAttributes) -> io::Result<()> {
+        for (i, Vertex([x, y, z])) in self.vertices.iter().enumerate() {
+            write!(file, "{} {} {}", x, y, z)?;
+            if let Some(normals) = attributes.normals {
+                let Vertex([nx, ny, nz]) = normals[i];
+                write!(file, " {} {} {}", nx, ny, nz)?;
+            }
+            if let Some(colors) = attributes.colors {
+                let [r, g, b] = colors[i];
+                write!(file, " {} {} {}", r, g, b)?;
+            }
+            writeln!(file)?;
+        }
+
+        for (i, face) in self.faces.iter().enumerate() {
+            write!(file, "{}", face.len())?;
+            for index in face.indices().iter() {
+                write!(file, " {}", index)?;
+            }
+            for (_, values) in attributes.face_properties.iter() {
+                write!(file, " {}", values[i])?;
+            }
+            writeln!(file)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_ply_binary<W: Write>(
+            &self, file: &mut W, attributes: &PlyAttributes) -> io::Result<()> {
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            write_floats(file, vertex)?;
+            if let Some(normals) = attributes.normals {
+                write_floats(file, &normals[i])?;
+            }
+            if let Some(colors) = attributes.colors {
+                file.write_all(&colors[i])?;
+            }
+        }
+
+        for (i, face) in self.faces.iter().enumerate() {
+            file.write_all(&[face.len() as u8])?;
+            for index in face.indices().iter() {
+                file.write_all(&(*index as i32).to_le_bytes())?;
+            }
+            for (_, values) in attributes.face_properties.iter() {
+                file.write_all(&values[i].to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_floats<W: Write>(file: &mut W, vector: &Vertex) -> io::Result<()> {
+    let Vertex(components) = vector;
+    for component in components.iter() {
+        file.write_all(&component.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn check_length(label: &str, actual: usize, expected: usize) -> io::Result<()> {
+    if actual != expected {
+        return Err(invalid_input(format!(
+            "expected {} {}, found {}", expected, label, actual)));
+    }
+
+    Ok(())
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    /// An element declared in the header
+    struct Element {
+        name: String,
+        count: usize,
+        properties: usize,
+    }
+
+    /// The elements of the header, and the body after it
+    fn split_header(bytes: &[u8]) -> (Vec<Element>, &[u8]) {
+        let end = b"end_header\n";
+        let body_start = bytes.windows(end.len())
+            .position(|window| window == end)
+            .unwrap() + end.len();
+        let header = std::str::from_utf8(&bytes[..body_start]).unwrap();
+
+        let mut elements: Vec<Element> = Vec::new();
+        for line in header.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens[0] {
+                "element" => elements.push(Element {
+                    name: tokens[1].to_string(),
+                    count: tokens[2].parse().unwrap(),
+                    properties: 0,
+                }),
+                "property" => elements.last_mut().unwrap().properties += 1,
+                _ => {}
+            }
+        }
+
+        (elements, &bytes[body_start..])
+    }
+
+    /// The cube with a normal and color for each vertex and a strand for
+    /// each face
+    fn write(encoding: Encoding) -> Vec<u8> {
+        let normals = vec![Vertex([0.0, 0.0, 1.0]); 8];
+        let colors = vec![[255, 128, 0]; 8];
+        let strands = vec![7; 6];
+        let attributes = PlyAttributes {
+            colors: Some(&colors),
+            normals: Some(&normals),
+            face_properties: vec![("strand", &strands)],
+        };
+
+        let mut bytes: Vec<u8> = Vec::new();
+        cube().write_ply(&mut bytes, encoding, &attributes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn ascii_header_counts_match_the_body() {
+        let bytes = write(Encoding::Ascii);
+        assert!(bytes.starts_with(b"ply\nformat ascii 1.0\n"));
+        let (elements, body) = split_header(&bytes);
+        let [vertex, face] = [&elements[0], &elements[1]];
+        assert_eq!((vertex.name.as_str(), vertex.count), ("vertex", 8));
+        assert_eq!((face.name.as_str(), face.count), ("face", 6));
+
+        let lines: Vec<&str> = std::str::from_utf8(body).unwrap().lines().collect();
+        assert_eq!(lines.len(), vertex.count + face.count);
+        for line in lines[..vertex.count].iter() {
+            assert_eq!(line.split_whitespace().count(), vertex.properties);
+        }
+        for line in lines[vertex.count..].iter() {
+            // The list of corners starts with its length
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(tokens[0], "4");
+            assert_eq!(tokens.len(), 1 + 4 + face.properties - 1);
+            assert_eq!(tokens.last(), Some(&"7"));
+        }
+    }
+
+    #[test]
+    fn binary_header_counts_match_the_body() {
+        let bytes = write(Encoding::Binary);
+        assert!(bytes.starts_with(b"ply\nformat binary_little_endian 1.0\n"));
+        let (elements, body) = split_header(&bytes);
+        assert_eq!((elements[0].count, elements[1].count), (8, 6));
+
+        // x y z nx ny nz as floats, then red green blue as bytes
+        let vertex_size = 6 * 4 + 3;
+        // The corner count, 4 corner indices and the strand
+        let face_size = 1 + 4 * 4 + 4;
+        assert_eq!(body.len(), 8 * vertex_size + 6 * face_size);
+
+        let faces = &body[8 * vertex_size..];
+        for (face, record) in cube().faces().iter().zip(faces.chunks(face_size)) {
+            assert_eq!(record[0], 4);
+            let ints: Vec<i32> = record[1..].chunks(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            let indices: Vec<i32> = face.indices().iter().map(|i| *i as i32).collect();
+            assert_eq!(ints[..4], indices[..]);
+            assert_eq!(ints[4], 7);
+        }
+    }
+
+    #[test]
+    fn attributes_of_the_wrong_length_are_rejected() {
+        let strands = vec![0; 5];
+        let attributes = PlyAttributes {
+            face_properties: vec![("strand", &strands)],
+            ..PlyAttributes::default()
+        };
+        let result = cube().write_ply(Vec::new(), Encoding::Ascii, &attributes);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -1,13 +1,54 @@
 use std::fmt::{Debug, Formatter, Result};
 
-#[derive(Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct Vertex(pub [f32; 3]);
 
 impl Vertex {
     pub fn dot(&self, other: &Vertex) -> f32 {
         let Vertex([x1, y1, z1]) = self;
         let Vertex([x2, y2, z2]) = other;
-        return x1 * x2 + y1 * y2 + z1 * z2;
+        x1 * x2 + y1 * y2 + z1 * z2
+    }
+
+    pub fn add(&self, other: &Vertex) -> Vertex {
+        let Vertex([x1, y1, z1]) = self;
+        let Vertex([x2, y2, z2]) = other;
+        Vertex([x1 + x2, y1 + y2, z1 + z2])
+    }
+
+    pub fn sub(&self, other: &Vertex) -> Vertex {
+        let Vertex([x1, y1, z1]) = self;
+        let Vertex([x2, y2, z2]) = other;
+        Vertex([x1 - x2, y1 - y2, z1 - z2])
+    }
+
+    pub fn scale(&self, factor: f32) -> Vertex {
+        let Vertex([x, y, z]) = self;
+        Vertex([x * factor, y * factor, z * factor])
+    }
+
+    pub fn cross(&self, other: &Vertex) -> Vertex {
+        let Vertex([x1, y1, z1]) = self;
+        let Vertex([x2, y2, z2]) = other;
+        Vertex([
+            y1 * z2 - z1 * y2,
+            z1 * x2 - x1 * z2,
+            x1 * y2 - y1 * x2
+        ])
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Scale to unit length. The zero vector is returned unchanged.
+    pub fn normalize(&self) -> Vertex {
+        let length = self.length();
+        if length == 0.0 {
+            *self
+        } else {
+            self.scale(1.0 / length)
+        }
     }
 }
 
@@ -18,9 +59,74 @@ impl Debug for Vertex {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Face {
     Triangle([usize; 3]),
     Quad([usize; 4]), 
+    /// Any polygon with 5 or more vertices
+    Polygon(Vec<usize>),
 }
 
+impl Face {
+    /**
+     * Make a face from a list of indices, using the smallest variant that
+     * fits. The list must have at least 3 indices.
+     */
+    pub fn from_indices(indices: Vec<usize>) -> Self {
+        match indices[..] {
+            [v1, v2, v3] => Face::Triangle([v1, v2, v3]),
+            [v1, v2, v3, v4] => Face::Quad([v1, v2, v3, v4]),
+            _ => Face::Polygon(indices)
+        }
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        match self {
+            Face::Triangle(vertices) => &vertices[..],
+            Face::Quad(vertices) => &vertices[..],
+            Face::Polygon(vertices) => &vertices[..],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices().is_empty()
+    }
+
+    /// Apply a function to every index, keeping the shape of the face
+    pub fn map<F: Fn(usize) -> usize>(&self, f: F) -> Self {
+        match self {
+            Face::Triangle([v1, v2, v3]) => Face::Triangle([f(*v1), f(*v2), f(*v3)]),
+            Face::Quad([v1, v2, v3, v4])
+                => Face::Quad([f(*v1), f(*v2), f(*v3), f(*v4)]),
+            Face::Polygon(vertices)
+                => Face::Polygon(vertices.iter().map(|v| f(*v)).collect()),
+        }
+    }
+
+    pub fn offset(&self, n: usize) -> Self {
+        self.map(|v| v + n)
+    }
+
+    /// The same face wound the other way, starting from the same corner
+    pub fn reversed(&self) -> Self {
+        let indices = self.indices();
+        let mut reversed = vec![indices[0]];
+        reversed.extend(indices[1..].iter().rev());
+        Face::from_indices(reversed)
+    }
+
+    /**
+     * Split the face into a fan of triangles around the first vertex. This
+     * keeps the winding order, but is only a good split for convex faces.
+     */
+    pub fn fan_triangles(&self) -> Vec<[usize; 3]> {
+        let indices = self.indices();
+        (1..indices.len() - 1).map(|i| {
+            [indices[0], indices[i], indices[i + 1]]
+        }).collect()
+    }
+}
@@ -0,0 +1,770 @@
+use std::collections::HashMap;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::Mesh;
+use crate::measure::bounding_box;
+
+/// How much dual contouring pulls vertices towards the middle of the
+/// surface in their cell, which keeps flat areas from drifting
+const REGULARIZATION: f32 = 0.05;
+
+/**
+ * Rays for the inside test are moved off the grid by this fraction of a
+ * voxel, so they don't run exactly through the edges of the tiles, which
+ * sit on round numbers
+ */
+const RAY_JITTER: [f32; 2] = [0.000_123_7, 0.000_291_3];
+
+/**
+ * How far the grid is shifted off the bounding box along each axis, as a
+ * fraction of a voxel. They differ so diagonal faces don't line up with
+ * the grid either
+ */
+const GRID_OFFSET: [f32; 3] = [0.371_9, 0.563_2, 0.146_7];
+
+/// How close to the ends of a grid edge a crossing can be, as a fraction of
+/// the edge
+const CROSSING_MARGIN: f32 = 0.01;
+
+/**
+ * The faces of a cell as corner numbers, counterclockwise seen from
+ * outside. Corner c is at offset (c & 1, c >> 1 & 1, c >> 2 & 1).
+ */
+const CELL_FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+/// How to turn the distance grid back into a surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Contouring {
+    /// A vertex on every grid edge the surface crosses. Sharp edges get
+    /// beveled
+    MarchingCubes,
+    /// A vertex in every cell the surface passes through, placed to fit
+    /// the surface there. This keeps sharp edges and makes quads, but very
+    /// thin parts can come out non-manifold
+    DualContouring,
+}
+
+/// Settings for Mesh::remesh()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RemeshOptions {
+    /// Edge length of a voxel. Details smaller than this are lost
+    pub voxel_size: f32,
+    pub contouring: Contouring,
+    /// Radius of the smooth union. Creases where surfaces meet, like where
+    /// strands cross or tiles overlap, are filled in with a rounded fillet
+    /// of this radius, and gaps narrower than twice this are closed. 0
+    /// keeps the surface as it is
+    pub smooth_union_radius: f32,
+}
+
+impl Default for RemeshOptions {
+    fn default() -> Self {
+        Self {
+            voxel_size: 1.0 / 16.0,
+            contouring: Contouring::MarchingCubes,
+            smooth_union_radius: 0.0,
+        }
+    }
+}
+
+impl Mesh {
+    /**
+     * Rebuild the surface from scratch: sample the signed distance to the
+     * mesh on a grid of voxels, then extract the surface where the
+     * distance is 0. Overlapping and intersecting pieces merge into one
+     * solid, and tile seams disappear, so the result is watertight even if
+     * the input isn't.
+     *
+     * Inside and outside are decided by counting faces along rays, so the
+     * faces must point outward (see orient_outward()), but the mesh doesn't
+     * have to be welded or closed where pieces overlap. The result has no
+     * uvs, normals, names or provenance.
+     */
+    pub fn remesh(&self, options: &RemeshOptions) -> Self {
+        let (min, max) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return self.clone()
+        };
+        let spacing = options.voxel_size;
+        let radius = options.smooth_union_radius.max(0.0);
+
+        // Distances are only exact this close to the surface. That needs
+        // to reach past the smooth union, and a little further for the
+        // contouring
+        let band = radius + 2.0 * spacing;
+        let mut grid = Grid::new(&min, &max, spacing, band + spacing);
+
+        let triangles: Vec<[usize; 3]> = self.faces.iter()
+            .flat_map(|face| face.fan_triangles())
+            .collect();
+        let distances = grid.distances(&self.vertices, &triangles, band);
+        let inside = grid.inside(&self.vertices, &triangles);
+        grid.values = distances.iter().zip(inside.iter())
+            .map(|(distance, inside)| if *inside { -distance } else { *distance })
+            .collect();
+
+        if radius > 0.0 {
+            grid.close(radius, band);
+        }
+
+        let (vertices, faces) = match options.contouring {
+            Contouring::MarchingCubes => grid.marching_cubes(),
+            Contouring::DualContouring => grid.dual_contouring(),
+        };
+        Self::from_geometry(vertices, faces)
+    }
+}
+
+/// Signed distances sampled at the points of a regular grid
+struct Grid {
+    origin: Vertex,
+    spacing: f32,
+    /// Number of points along each axis
+    dims: [usize; 3],
+    /// Negative inside the surface
+    values: Vec<f32>,
+}
+
+impl Grid {
+    /// A grid covering the box from min to max, plus padding on all sides
+    fn new(min: &Vertex, max: &Vertex, spacing: f32, padding: f32) -> Self {
+        // The tiles' vertices sit on round numbers, so their faces
+        // would run right through grid points if the grid lined up with
+        // them. Values of 0 there would fill the surface with slivers
+        let [dx, dy, dz] = GRID_OFFSET;
+        let origin = min.sub(&Vertex([padding, padding, padding]))
+            .sub(&Vertex([dx, dy, dz]).scale(spacing));
+        let Vertex(size) = max.add(&Vertex([padding, padding, padding])).sub(&origin);
+
+        let mut dims = [0; 3];
+        for (count, length) in dims.iter_mut().zip(size.iter()) {
+            *count = (length / spacing).ceil() as usize + 1;
+        }
+
+        Self {
+            origin,
+            spacing,
+            dims,
+            values: vec![0.0; dims[0] * dims[1] * dims[2]],
+        }
+    }
+
+    fn index(&self, [i, j, k]: [usize; 3]) -> usize {
+        i + self.dims[0] * (j + self.dims[1] * k)
+    }
+
+    fn point(&self, [i, j, k]: [usize; 3]) -> Vertex {
+        self.origin.add(&Vertex([i as f32, j as f32, k as f32]).scale(self.spacing))
+    }
+
+    /// The range of grid points inside the box from min to max
+    fn points_between(&self, min: &Vertex, max: &Vertex) -> ([usize; 3], [usize; 3]) {
+        let Vertex(low) = min.sub(&self.origin);
+        let Vertex(high) = max.sub(&self.origin);
+        let mut first = [0; 3];
+        let mut last = [0; 3];
+        for axis in 0..3 {
+            let top = self.dims[axis] as f32 - 1.0;
+            first[axis] = (low[axis] / self.spacing).ceil().max(0.0).min(top) as usize;
+            last[axis] = (high[axis] / self.spacing).floor().max(0.0).min(top) as usize;
+        }
+
+        (first, last)
+    }
+
+    /**
+     * The unsigned distance from every grid point to the nearest triangle.
+     * Only points within band of a triangle are measured; the rest get
+     * band.
+     */
+    fn distances(&self, vertices: &[Vertex], triangles: &[[usize; 3]], band: f32)
+            -> Vec<f32> {
+        let mut distances = vec![band; self.values.len()];
+        let margin = Vertex([band, band, band]);
+        for triangle in triangles.iter() {
+            let corners = triangle.map(|v| vertices[v]);
+            let (min, max) = bounding_box(&corners);
+            let (first, last) = self.points_between(&min.sub(&margin), &max.add(&margin));
+
+            for k in first[2]..=last[2] {
+                for j in first[1]..=last[1] {
+                    for i in first[0]..=last[0] {
+                        let point = self.point([i, j, k]);
+                        let closest = closest_point_on_triangle(&point, &corners);
+                        let distance = point.sub(&closest).length();
+                        let index = self.index([i, j, k]);
+                        if distance < distances[index] {
+                            distances[index] = distance;
+                        }
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
+    /**
+     * Whether each grid point is inside the surface, using the nonzero
+     * winding rule along rays in the x direction. A face pointing against
+     * the ray is where it goes in, and one pointing along it is where it
+     * comes out, so points inside overlapping pieces still count as inside.
+     *
+     * A ray that goes in through a face but leaves through a hole would
+     * count everything after the hole as inside, so rays are followed from
+     * both ends and have to agree. That keeps holes from leaking out to the
+     * edge of the grid, which would cut the surface open.
+     */
+    fn inside(&self, vertices: &[Vertex], triangles: &[[usize; 3]]) -> Vec<bool> {
+        let [width, height, depth] = self.dims;
+        let mut crossings: Vec<Vec<(f32, i32)>> = vec![Vec::new(); height * depth];
+        let jitter = Vertex([0.0, RAY_JITTER[0], RAY_JITTER[1]]).scale(self.spacing);
+
+        for triangle in triangles.iter() {
+            let corners = triangle.map(|v| vertices[v]);
+            let [a, b, c] = corners;
+            let Vertex([normal_x, _, _]) = b.sub(&a).cross(&c.sub(&a));
+            if normal_x == 0.0 {
+                continue;
+            }
+            let winding = if normal_x < 0.0 { 1 } else { -1 };
+
+            // Widened a little to include rays that only hit because of
+            // the jitter
+            let (min, max) = bounding_box(&corners);
+            let (first, last) = self.points_between(&min.sub(&jitter), &max);
+            for k in first[2]..=last[2] {
+                for j in first[1]..=last[1] {
+                    let Vertex([_, y, z]) = self.point([0, j, k]);
+                    let Vertex([_, dy, dz]) = jitter;
+                    let ray = [y + dy, z + dz];
+                    if let Some(x) = ray_crossing(ray, &corners) {
+                        crossings[j + height * k].push((x, winding));
+                    }
+                }
+            }
+        }
+
+        let mut inside = vec![false; self.values.len()];
+        for (column, hits) in crossings.iter_mut().enumerate() {
+            hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let (j, k) = (column % height, column / height);
+            let total: i32 = hits.iter().map(|(_, winding)| winding).sum();
+            let mut winding = 0;
+            let mut next_hit = 0;
+            for i in 0..width {
+                let Vertex([x, _, _]) = self.point([i, j, k]);
+                while next_hit < hits.len() && hits[next_hit].0 < x {
+                    winding += hits[next_hit].1;
+                    next_hit += 1;
+                }
+                // Counting from the other end of the ray gives
+                // winding - total
+                inside[self.index([i, j, k])] = winding > 0 && winding - total > 0;
+            }
+        }
+
+        inside
+    }
+
+    /**
+     * A smooth union by morphological closing: grow the surface by radius,
+     * then shrink it back by measuring the distance to the grown surface.
+     * Convex parts come back where they were, but creases and narrow gaps
+     * that the grown surface filled in stay filled, with rounded edges.
+     */
+    fn close(&mut self, radius: f32, band: f32) {
+        for value in self.values.iter_mut() {
+            *value -= radius;
+        }
+
+        let (vertices, faces) = self.marching_cubes();
+        let triangles: Vec<[usize; 3]> = faces.iter()
+            .flat_map(|face| face.fan_triangles())
+            .collect();
+        let distances = self.distances(&vertices, &triangles, band);
+        for (value, distance) in self.values.iter_mut().zip(distances.iter()) {
+            let signed = if *value < 0.0 { -distance } else { *distance };
+            *value = signed + radius;
+        }
+    }
+
+    /// The value at each corner of the cell, numbered like CELL_FACES
+    fn cell_values(&self, cell: [usize; 3]) -> [f32; 8] {
+        let mut values = [0.0; 8];
+        for (corner, value) in values.iter_mut().enumerate() {
+            *value = self.values[self.index(corner_point(cell, corner))];
+        }
+        values
+    }
+
+    /**
+     * Marching cubes. Rather than looking up each cell's triangles in a
+     * table, the outline of the surface is traced around the faces of the
+     * cell and each closed loop is filled with triangles. Ambiguous faces
+     * are decided by the value in their middle, which neighboring cells
+     * agree on, so the outlines of neighboring cells match up.
+     */
+    fn marching_cubes(&self) -> (Vec<Vertex>, Vec<Face>) {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut faces: Vec<Face> = Vec::new();
+        // Vertices are shared between cells by the grid edge they're on
+        let mut edge_vertices: HashMap<usize, usize> = HashMap::new();
+
+        for cell in self.cells() {
+            let values = self.cell_values(cell);
+            let inside = values.map(|value| value < 0.0);
+            if inside.iter().all(|x| *x) || inside.iter().all(|x| !x) {
+                continue;
+            }
+
+            let mut segments: Vec<([usize; 2], [usize; 2])> = Vec::new();
+            for face in CELL_FACES.iter() {
+                face_segments(face, &values, &mut segments);
+            }
+
+            // Each crossing starts one segment and ends another, so they
+            // link up into loops. An outline that doesn't close, which only
+            // NaN values could cause, is left out
+            'outlines: while let Some((start, mut end)) = segments.pop() {
+                let mut outline = vec![start];
+                while end != start {
+                    let next = match segments.iter()
+                            .position(|(from, _)| *from == end) {
+                        Some(next) => next,
+                        None => continue 'outlines,
+                    };
+                    outline.push(end);
+                    end = segments.swap_remove(next).1;
+                }
+
+                let corners: Vec<usize> = outline.iter().map(|edge| {
+                    let key = self.edge_key(cell, *edge);
+                    *edge_vertices.entry(key).or_insert_with(|| {
+                        vertices.push(self.edge_crossing(cell, *edge, &values));
+                        vertices.len() - 1
+                    })
+                }).collect();
+                triangulate_outline(&outline, &corners, &mut vertices, &mut faces);
+            }
+        }
+
+        (vertices, faces)
+    }
+
+    /**
+     * Dual contouring. Each cell the surface passes through gets one
+     * vertex where the planes of the surface at its crossings meet, and
+     * every grid edge the surface crosses becomes a quad joining the four
+     * cells around it.
+     */
+    fn dual_contouring(&self) -> (Vec<Vertex>, Vec<Face>) {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut cell_vertices: HashMap<[usize; 3], usize> = HashMap::new();
+        for cell in self.cells() {
+            if let Some(vertex) = self.cell_vertex(cell) {
+                vertices.push(vertex);
+                cell_vertices.insert(cell, vertices.len() - 1);
+            }
+        }
+
+        let mut faces: Vec<Face> = Vec::new();
+        for cell in self.cells() {
+            let start_inside = self.values[self.index(cell)] < 0.0;
+            for axis in 0..3 {
+                let mut end = cell;
+                end[axis] += 1;
+                if (self.values[self.index(end)] < 0.0) == start_inside {
+                    continue;
+                }
+
+                // The cells around the edge, counterclockwise around the
+                // axis. The outermost cells never have a crossing, so the
+                // edge can't be on the low side of the grid
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                if cell[u] == 0 || cell[v] == 0 {
+                    continue;
+                }
+                let around = [(1, 1), (0, 1), (0, 0), (1, 0)].iter()
+                    .map(|(du, dv)| {
+                        let mut neighbor = cell;
+                        neighbor[u] -= du;
+                        neighbor[v] -= dv;
+                        cell_vertices.get(&neighbor).copied()
+                    })
+                    .collect::<Option<Vec<usize>>>();
+
+                if let Some(quad) = around {
+                    let face = Face::from_indices(quad);
+                    faces.push(if start_inside { face } else { face.reversed() });
+                }
+            }
+        }
+
+        (vertices, faces)
+    }
+
+    /**
+     * The point that best fits the planes of the surface where it crosses
+     * the edges of the cell, kept inside the cell. None if the surface
+     * doesn't pass through the cell.
+     */
+    fn cell_vertex(&self, cell: [usize; 3]) -> Option<Vertex> {
+        let values = self.cell_values(cell);
+        let mut crossings: Vec<(Vertex, Vertex)> = Vec::new();
+        for corner in 0..8 {
+            for bit in [1, 2, 4].iter() {
+                let other = corner | bit;
+                if other == corner || (values[corner] < 0.0) == (values[other] < 0.0) {
+                    continue;
+                }
+
+                let edge = [corner, other];
+                let t = values[corner] / (values[corner] - values[other]);
+                let normal = self.gradient(corner_point(cell, corner)).scale(1.0 - t)
+                    .add(&self.gradient(corner_point(cell, other)).scale(t))
+                    .normalize();
+                crossings.push((self.edge_crossing(cell, edge, &values), normal));
+            }
+        }
+        if crossings.is_empty() {
+            return None;
+        }
+
+        // Least squares, relative to the average crossing, with a little
+        // pull towards it in case the planes are nearly parallel
+        let mass_point = crossings.iter()
+            .fold(Vertex([0.0, 0.0, 0.0]), |sum, (point, _)| sum.add(point))
+            .scale(1.0 / crossings.len() as f32);
+        let mut matrix = [[0.0; 3]; 3];
+        let mut rhs = [0.0; 3];
+        for (point, Vertex(normal)) in crossings.iter() {
+            let offset = Vertex(*normal).dot(&point.sub(&mass_point));
+            for i in 0..3 {
+                for j in 0..3 {
+                    matrix[i][j] += normal[i] * normal[j];
+                }
+                rhs[i] += normal[i] * offset;
+            }
+        }
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] += REGULARIZATION;
+        }
+
+        let Vertex(low) = self.point(cell);
+        let Vertex(mut result) = mass_point.add(&Vertex(solve(&matrix, &rhs)));
+        for axis in 0..3 {
+            result[axis] = result[axis].max(low[axis]).min(low[axis] + self.spacing);
+        }
+
+        Some(Vertex(result))
+    }
+
+    /// The gradient of the values at a grid point, by central differences
+    fn gradient(&self, point: [usize; 3]) -> Vertex {
+        let mut gradient = [0.0; 3];
+        for (axis, component) in gradient.iter_mut().enumerate() {
+            let mut before = point;
+            let mut after = point;
+            before[axis] = before[axis].saturating_sub(1);
+            after[axis] = (after[axis] + 1).min(self.dims[axis] - 1);
+
+            let step = (after[axis] - before[axis]) as f32 * self.spacing;
+            *component = (self.values[self.index(after)]
+                - self.values[self.index(before)]) / step;
+        }
+
+        Vertex(gradient)
+    }
+
+    /// The lowest grid point of every cell
+    fn cells(&self) -> impl Iterator<Item = [usize; 3]> {
+        let [width, height, depth] = self.dims;
+        (0..depth - 1).flat_map(move |k| {
+            (0..height - 1).flat_map(move |j| {
+                (0..width - 1).map(move |i| [i, j, k])
+            })
+        })
+    }
+
+    /// A number for the grid edge between two corners of a cell
+    fn edge_key(&self, cell: [usize; 3], edge: [usize; 2]) -> usize {
+        let [a, b] = edge;
+        let lower = a.min(b);
+        let axis = (a ^ b).trailing_zeros() as usize;
+        3 * self.index(corner_point(cell, lower)) + axis
+    }
+
+    /**
+     * Where the values cross 0 on an edge of the cell. Crossings are kept
+     * a little way from the ends, or a grid point just barely inside or
+     * outside would be cut off by a triangle too small to have an area.
+     */
+    fn edge_crossing(&self, cell: [usize; 3], edge: [usize; 2], values: &[f32; 8])
+            -> Vertex {
+        let [a, b] = edge;
+        let t = (values[a] / (values[a] - values[b]))
+            .clamp(CROSSING_MARGIN, 1.0 - CROSSING_MARGIN);
+        let start = self.point(corner_point(cell, a));
+        let end = self.point(corner_point(cell, b));
+        start.add(&end.sub(&start).scale(t))
+    }
+}
+
+/**
+ * Fill a loop of crossings with triangles. The only edges on the faces of
+ * the cell may be the loop's own segments, which the neighboring cell has
+ * too. Any other edge there would be shared with nothing, or with the
+ * neighbor's triangles, which leaves holes or non-manifold edges. A fan
+ * works if its corner doesn't share a cell face with any corner it
+ * connects to. If no corner works, the fan goes around a new vertex in
+ * the middle of the loop instead.
+ */
+fn triangulate_outline(
+        outline: &[[usize; 2]],
+        corners: &[usize],
+        vertices: &mut Vec<Vertex>,
+        faces: &mut Vec<Face>) {
+    let n = outline.len();
+    let fan_corner = (0..n).find(|start| {
+        (2..n - 1).all(|k| !on_same_face(outline[*start], outline[(start + k) % n]))
+    });
+
+    match fan_corner {
+        Some(start) => {
+            for k in 1..n - 1 {
+                faces.push(Face::Triangle([
+                    corners[start],
+                    corners[(start + k) % n],
+                    corners[(start + k + 1) % n],
+                ]));
+            }
+        },
+        None => {
+            let sum = corners.iter()
+                .fold(Vertex([0.0, 0.0, 0.0]), |sum, v| sum.add(&vertices[*v]));
+            vertices.push(sum.scale(1.0 / n as f32));
+            let middle = vertices.len() - 1;
+            for i in 0..n {
+                faces.push(Face::Triangle([middle, corners[i], corners[(i + 1) % n]]));
+            }
+        }
+    }
+}
+
+/// Whether two edges of a cell lie on the same face of it
+fn on_same_face(first: [usize; 2], second: [usize; 2]) -> bool {
+    let corners = [first[0], first[1], second[0], second[1]];
+    [1, 2, 4].iter().any(|bit| {
+        corners.iter().all(|c| c & bit != 0) || corners.iter().all(|c| c & bit == 0)
+    })
+}
+
+fn corner_point([i, j, k]: [usize; 3], corner: usize) -> [usize; 3] {
+    [i + (corner & 1), j + (corner >> 1 & 1), k + (corner >> 2 & 1)]
+}
+
+/**
+ * The pieces of the surface's outline on one face of a cell, as pairs of
+ * cell edges with the lower corner first. Walking counterclockwise around
+ * the face, each piece goes from the edge where the walk enters the inside
+ * to the one where it leaves.
+ */
+fn face_segments(
+        face: &[usize; 4],
+        values: &[f32; 8],
+        segments: &mut Vec<([usize; 2], [usize; 2])>) {
+    let inside = |i: usize| values[face[i % 4]] < 0.0;
+    let edge = |i: usize| {
+        let (a, b) = (face[i % 4], face[(i + 1) % 4]);
+        [a.min(b), a.max(b)]
+    };
+    let crossings: Vec<usize> = (0..4)
+        .filter(|i| inside(*i) != inside(i + 1))
+        .collect();
+
+    match crossings[..] {
+        [first, second] => {
+            if inside(first + 1) {
+                segments.push((edge(first), edge(second)));
+            } else {
+                segments.push((edge(second), edge(first)));
+            }
+        },
+        [_, _, _, _] => {
+            // The corners are alternately inside and outside. If the middle
+            // of the face is outside, the inside corners are cut off
+            // separately, otherwise the outside ones are. The values are
+            // added in sorted order, so the cell on the other side of the
+            // face, which goes around it the other way, gets the same sum
+            let mut corners = face.map(|c| values[c]);
+            corners.sort_by(f32::total_cmp);
+            let cut_inside = corners.iter().sum::<f32>() >= 0.0;
+            for i in 0..4 {
+                if inside(i) == cut_inside {
+                    let (before, after) = (edge(i + 3), edge(i));
+                    segments.push(
+                        if cut_inside { (before, after) } else { (after, before) });
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+/**
+ * Where a ray in the x direction through (y, z) hits the triangle, if it
+ * does
+ */
+fn ray_crossing([y, z]: [f32; 2], [a, b, c]: &[Vertex; 3]) -> Option<f32> {
+    let (Vertex([ax, ay, az]), Vertex([bx, by, bz]), Vertex([cx, cy, cz])) = (a, b, c);
+
+    // Barycentric coordinates in the yz plane
+    let area = (by - ay) * (cz - az) - (bz - az) * (cy - ay);
+    let u = ((by - y) * (cz - z) - (bz - z) * (cy - y)) / area;
+    let v = ((cy - y) * (az - z) - (cz - z) * (ay - y)) / area;
+    let w = 1.0 - u - v;
+    if u < 0.0 || v < 0.0 || w < 0.0 {
+        return None;
+    }
+
+    Some(u * ax + v * bx + w * cx)
+}
+
+/// The closest point to p on the triangle, from Real-Time Collision Detection
+fn closest_point_on_triangle(p: &Vertex, [a, b, c]: &[Vertex; 3]) -> Vertex {
+    let ab = b.sub(a);
+    let ac = c.sub(a);
+    let ap = p.sub(a);
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+
+    let bp = p.sub(b);
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a.add(&ab.scale(d1 / (d1 - d3)));
+    }
+
+    let cp = p.sub(c);
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a.add(&ac.scale(d2 / (d2 - d6)));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b.add(&c.sub(b).scale((d4 - d3) / ((d4 - d3) + (d5 - d6))));
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    a.add(&ab.scale(vb * denominator)).add(&ac.scale(vc * denominator))
+}
+
+/// Solve a 3x3 linear system with Cramer's rule
+fn solve(matrix: &[[f32; 3]; 3], rhs: &[f32; 3]) -> [f32; 3] {
+    let full = determinant(matrix);
+    if full.abs() < 1e-12 {
+        return [0.0; 3];
+    }
+
+    let mut result = [0.0; 3];
+    for (column, value) in result.iter_mut().enumerate() {
+        let mut replaced = *matrix;
+        for (row, entry) in replaced.iter_mut().zip(rhs.iter()) {
+            row[column] = *entry;
+        }
+        *value = determinant(&replaced) / full;
+    }
+
+    result
+}
+
+fn determinant(m: &[[f32; 3]; 3]) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{KnotGridBuilder, MirrorPolicy};
+    use crate::tileset::Tileset;
+    use crate::test_meshes::cube;
+
+    fn knot_grid() -> Mesh {
+        let tileset = Tileset::load(concat!(env!("CARGO_MANIFEST_DIR"), "/data/sturdy"))
+            .unwrap();
+        KnotGridBuilder::new(tileset)
+            .dimensions(3, 3, 3)
+            .mirrors(MirrorPolicy::Random(0.78))
+            .seed(5)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn marching_cubes_is_closed_and_manifold() {
+        let grid = knot_grid();
+        for voxels in [4, 6, 8, 12].iter() {
+            for radius in [0.0, 0.1].iter() {
+                let options = RemeshOptions {
+                    voxel_size: 1.0 / *voxels as f32,
+                    smooth_union_radius: *radius,
+                    ..RemeshOptions::default()
+                };
+                let report = grid.remesh(&options).analyze();
+                assert!(report.is_clean(), "{} voxels, radius {}: {:?}",
+                    voxels, radius, report);
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_cubes_merge_into_one_solid() {
+        let mut mesh = cube();
+        mesh.add_geometry(&cube().translate(&[0.5, 0.0, 0.0]));
+        for contouring in [Contouring::MarchingCubes, Contouring::DualContouring].iter() {
+            let options = RemeshOptions {
+                voxel_size: 0.125,
+                contouring: *contouring,
+                ..RemeshOptions::default()
+            };
+            let remeshed = mesh.remesh(&options);
+            assert!(remeshed.analyze().is_clean(), "{:?}", contouring);
+            assert_eq!(remeshed.connected_components().len(), 1);
+            // Marching cubes bevels the edges, so some volume is lost
+            assert!((remeshed.volume() - 1.5).abs() < 0.1,
+                "{:?} volume {}", contouring, remeshed.volume());
+        }
+    }
+
+    #[test]
+    fn empty_mesh_stays_empty() {
+        let remeshed = Mesh::new().remesh(&RemeshOptions::default());
+        assert!(remeshed.faces().is_empty());
+    }
+}
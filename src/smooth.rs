@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+
+/// Vertices closer than this to the bounding box count as being on it
+const ON_BOUNDS: f32 = 1e-4;
+
+/// Settings for Mesh::smooth()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SmoothOptions {
+    /// Each iteration is one shrinking step and one inflating step
+    pub iterations: usize,
+    /// How far each vertex moves towards the average of its neighbors
+    pub lambda: f32,
+    /// The same for the inflating step. It must be negative and a bit
+    /// larger in magnitude than lambda. Set it to 0 for plain Laplacian
+    /// smoothing, which shrinks the mesh.
+    pub mu: f32,
+    /// Keep vertices on the faces of the bounding box where they are, so
+    /// the flat ends of the grid stay flat
+    pub pin_bounds: bool,
+    /// Keep vertices on boundary edges (holes) where they are
+    pub pin_boundary: bool,
+}
+
+impl Default for SmoothOptions {
+    fn default() -> Self {
+        Self {
+            iterations: 10,
+            lambda: 0.5,
+            mu: -0.53,
+            pin_bounds: true,
+            pin_boundary: true,
+        }
+    }
+}
+
+impl Mesh {
+    /**
+     * Taubin smoothing: alternately move every vertex towards the average
+     * of its neighbors by lambda, then away from it by mu. Plain Laplacian
+     * smoothing shrinks the mesh a little every step, but the second step
+     * undoes the shrinking while the noise stays smoothed out.
+     *
+     * Neighbors are found by vertex index, so weld the mesh first. If the
+     * mesh had normals, they are replaced with smooth ones.
+     */
+    pub fn smooth(&self, options: &SmoothOptions) -> Self {
+        let neighbors = self.vertex_neighbors();
+        let pinned = self.pinned_vertices(options);
+
+        let mut positions = self.vertices.clone();
+        for _ in 0..options.iterations {
+            for factor in [options.lambda, options.mu].iter() {
+                if *factor != 0.0 {
+                    positions = laplacian_step(
+                        &positions, &neighbors, &pinned, *factor);
+                }
+            }
+        }
+
+        let result = Self {
+            vertices: positions,
+            ..self.clone()
+        };
+        if self.has_normals() {
+            result.with_smooth_normals(180.0)
+        } else {
+            result
+        }
+    }
+
+    /// The vertices sharing an edge with each vertex
+    fn vertex_neighbors(&self) -> Vec<Vec<usize>> {
+        let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); self.vertices.len()];
+        for face in self.faces.iter() {
+            let indices = face.indices();
+            for (i, a) in indices.iter().enumerate() {
+                let b = indices[(i + 1) % indices.len()];
+                if *a != b {
+                    neighbors[*a].push(b);
+                    neighbors[b].push(*a);
+                }
+            }
+        }
+
+        for list in neighbors.iter_mut() {
+            list.sort_unstable();
+            list.dedup();
+        }
+
+        neighbors
+    }
+
+    fn pinned_vertices(&self, options: &SmoothOptions) -> Vec<bool> {
+        let mut pinned = vec![false; self.vertices.len()];
+
+        if options.pin_bounds {
+            if let Some((Vertex(min), Vertex(max))) = self.bounding_box() {
+                for (vertex, pin) in self.vertices.iter().zip(pinned.iter_mut()) {
+                    let Vertex(components) = vertex;
+                    *pin = (0..3).any(|axis| {
+                        (components[axis] - min[axis]).abs() < ON_BOUNDS
+                            || (components[axis] - max[axis]).abs() < ON_BOUNDS
+                    });
+                }
+            }
+        }
+
+        if options.pin_boundary {
+            let mut edge_counts: HashMap<[usize; 2], usize> = HashMap::new();
+            for face in self.faces.iter() {
+                let indices = face.indices();
+                for (i, a) in indices.iter().enumerate() {
+                    let b = indices[(i + 1) % indices.len()];
+                    *edge_counts.entry([*a.min(&b), *a.max(&b)]).or_insert(0) += 1;
+                }
+            }
+
+            for ([a, b], count) in edge_counts {
+                if count == 1 {
+                    pinned[a] = true;
+                    pinned[b] = true;
+                }
+            }
+        }
+
+        pinned
+    }
+}
+
+/// Move every free vertex by factor towards the average of its neighbors
+fn laplacian_step(
+        positions: &[Vertex],
+        neighbors: &[Vec<usize>],
+        pinned: &[bool],
+        factor: f32) -> Vec<Vertex> {
+    positions.iter().enumerate().map(|(v, position)| {
+        if pinned[v] || neighbors[v].is_empty() {
+            return *position;
+        }
+
+        let sum = neighbors[v].iter().fold(Vertex([0.0, 0.0, 0.0]),
+            |sum, other| sum.add(&positions[*other]));
+        let average = sum.scale(1.0 / neighbors[v].len() as f32);
+        position.add(&average.sub(position).scale(factor))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subdivide::Subdivision;
+    use crate::test_meshes::cube;
+
+    fn unpinned(mu: f32) -> SmoothOptions {
+        SmoothOptions {
+            mu,
+            pin_bounds: false,
+            ..SmoothOptions::default()
+        }
+    }
+
+    /// A flat 4x4 grid of quads in the x = 0 plane
+    fn flat_grid() -> Mesh {
+        let mut square = cube();
+        square.faces.truncate(1);
+        square.face_attributes.truncate(1);
+        square.subdivide(Subdivision::CatmullClark, 2)
+    }
+
+    #[test]
+    fn taubin_shrinks_less_than_laplacian() {
+        let blob = cube().subdivide(Subdivision::CatmullClark, 2);
+        let volume = blob.volume();
+        let laplacian = blob.smooth(&unpinned(0.0)).volume();
+        let taubin = blob.smooth(&unpinned(SmoothOptions::default().mu)).volume();
+
+        assert!((volume - taubin).abs() < 0.2 * (volume - laplacian),
+            "volume {} became {} with Taubin and {} with Laplacian",
+            volume, taubin, laplacian);
+        assert!(blob.smooth(&unpinned(-0.53)).analyze().is_clean());
+    }
+
+    #[test]
+    fn bumps_are_smoothed_out() {
+        let mut grid = flat_grid();
+        let bump = (0..grid.vertices().len())
+            .find(|v| grid.vertices()[*v] == Vertex([0.0, 0.5, 0.5]))
+            .unwrap();
+        grid.vertices[bump] = Vertex([0.3, 0.5, 0.5]);
+
+        let smoothed = grid.smooth(&SmoothOptions::default());
+        let Vertex([height, _, _]) = smoothed.vertices()[bump];
+        assert!(height.abs() < 0.1, "bump is still {} high", height);
+    }
+
+    #[test]
+    fn pinned_vertices_stay_put() {
+        // Every corner of a cube is on its bounding box
+        let mesh = cube();
+        assert_eq!(mesh.smooth(&SmoothOptions::default()).vertices(), mesh.vertices());
+
+        // The edge of an open grid is its boundary
+        let grid = flat_grid();
+        let options = SmoothOptions {
+            pin_bounds: false,
+            ..SmoothOptions::default()
+        };
+        let smoothed = grid.smooth(&options);
+        for [a, b] in grid.analyze().boundary_edges.iter() {
+            assert_eq!(smoothed.vertices()[*a], grid.vertices()[*a]);
+            assert_eq!(smoothed.vertices()[*b], grid.vertices()[*b]);
+        }
+    }
+}
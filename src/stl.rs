@@ -0,0 +1,157 @@
+use std::io::{self, BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+use crate::error::MeshError;
+use crate::format::Encoding;
+
+impl Mesh {
+    /**
+     * Save the mesh as STL. STL only stores triangles, so quads and other
+     * polygons are split into triangles first. Each triangle gets a facet
+     * normal computed from its winding order.
+     */
+    pub fn save_stl<P: AsRef<Path>>(&self, fname: P, encoding: Encoding)
+            -> Result<(), MeshError> {
+        let fname = fname.as_ref().display().to_string();
+        let file = File::create(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        let writer = BufWriter::new(file);
+        let result = match encoding {
+            Encoding::Ascii => self.write_stl_ascii(writer),
+            Encoding::Binary => self.write_stl_binary(writer),
+        };
+        result.map_err(|error| MeshError::io(&fname, error))
+    }
+
+    pub fn write_stl_ascii<W: Write>(&self, mut file: W) -> io::Result<()> {
+        writeln!(file, "solid celtic_knot")?;
+        for triangle in self.stl_triangles() {
+            let [normal, a, b, c] = triangle;
+            writeln!(file, "  facet normal {}", format_stl_vector(&normal))?;
+            writeln!(file, "    outer loop")?;
+            for vertex in [a, b, c].iter() {
+                writeln!(file, "      vertex {}", format_stl_vector(vertex))?;
+            }
+            writeln!(file, "    endloop")?;
+            writeln!(file, "  endfacet")?;
+        }
+        writeln!(file, "endsolid celtic_knot")?;
+
+        file.flush()
+    }
+
+    pub fn write_stl_binary<W: Write>(&self, mut file: W) -> io::Result<()> {
+        let triangles = self.stl_triangles();
+        if triangles.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too many triangles for binary STL"));
+        }
+
+        // The header is free-form but must not start with "solid", or
+        // readers may mistake the file for ASCII STL
+        let mut header = [0u8; 80];
+        let label = b"binary STL from celtic-knots-3d";
+        header[..label.len()].copy_from_slice(label);
+        file.write_all(&header)?;
+        file.write_all(&(triangles.len() as u32).to_le_bytes())?;
+
+        for triangle in triangles.iter() {
+            for Vertex(components) in triangle.iter() {
+                for component in components.iter() {
+                    file.write_all(&component.to_le_bytes())?;
+                }
+            }
+            // attribute byte count, unused
+            file.write_all(&0u16.to_le_bytes())?;
+        }
+
+        file.flush()
+    }
+
+    /// Each triangle of the mesh as [normal, a, b, c]
+    fn stl_triangles(&self) -> Vec<[Vertex; 4]> {
+        let mut triangles: Vec<[Vertex; 4]> = Vec::new();
+        for face in self.faces.iter() {
+            for triangle in face.fan_triangles() {
+                let [a, b, c] = triangle;
+                triangles.push([
+                    self.triangle_normal(triangle),
+                    self.vertices[a],
+                    self.vertices[b],
+                    self.vertices[c],
+                ]);
+            }
+        }
+
+        triangles
+    }
+}
+
+fn format_stl_vector(vector: &Vertex) -> String {
+    let Vertex([x, y, z]) = vector;
+    format!("{:e} {:e} {:e}", x, y, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_meshes::cube;
+
+    /// The normal points away from the middle of the cube
+    fn faces_outward(normal: [f32; 3], corner: [f32; 3]) -> bool {
+        (0..3).map(|i| normal[i] * (corner[i] - 0.5)).sum::<f32>() > 0.0
+    }
+
+    #[test]
+    fn binary_stl_has_a_header_a_count_and_50_byte_records() {
+        let mut bytes: Vec<u8> = Vec::new();
+        cube().write_stl_binary(&mut bytes).unwrap();
+
+        // The 6 quads of the cube become 12 triangles
+        assert_eq!(bytes.len(), 80 + 4 + 12 * 50);
+        assert!(!bytes.starts_with(b"solid"));
+        assert_eq!(u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]), 12);
+
+        for record in bytes[84..].chunks(50) {
+            let floats: Vec<f32> = record[..48].chunks(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            let normal = [floats[0], floats[1], floats[2]];
+            let length: f32 = normal.iter().map(|x| x * x).sum();
+            assert!((length - 1.0).abs() < 1e-6);
+            assert!(faces_outward(normal, [floats[3], floats[4], floats[5]]));
+            assert_eq!(record[48..], [0, 0]);
+        }
+    }
+
+    #[test]
+    fn ascii_stl_has_one_facet_per_triangle() {
+        let mut bytes: Vec<u8> = Vec::new();
+        cube().write_stl_ascii(&mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+
+        assert_eq!(lines.first(), Some(&"solid celtic_knot"));
+        assert_eq!(lines.last(), Some(&"endsolid celtic_knot"));
+        let facets: Vec<&[&str]> = lines[1..lines.len() - 1].chunks(7).collect();
+        assert_eq!(facets.len(), 12);
+        for facet in facets {
+            let numbers = |line: &str, prefix: &str| -> [f32; 3] {
+                let values: Vec<f32> = line.strip_prefix(prefix).unwrap()
+                    .split_whitespace()
+                    .map(|value| value.parse().unwrap())
+                    .collect();
+                [values[0], values[1], values[2]]
+            };
+            let normal = numbers(facet[0], "facet normal");
+            assert_eq!(facet[1], "outer loop");
+            for line in facet[2..5].iter() {
+                assert!(faces_outward(normal, numbers(line, "vertex")));
+            }
+            assert_eq!(facet[5..], ["endloop", "endfacet"]);
+        }
+    }
+}
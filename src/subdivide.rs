@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::{Mesh, FaceAttributes};
+use crate::triangulate::Triangulation;
+
+/// Which subdivision surface to use
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Subdivision {
+    /// Catmull-Clark, which turns every face into quads
+    CatmullClark,
+    /// Loop, for triangle meshes. Other faces are triangulated first
+    Loop,
+}
+
+impl Mesh {
+    /**
+     * Smooth the mesh by subdividing it the given number of times. Each
+     * level multiplies the number of faces by about 4.
+     *
+     * Neighbors are found by vertex index, so weld the mesh first or every
+     * tile seam is treated as a boundary and the tiles pull apart. Boundary
+     * edges (and edges with more than two faces) are kept as creases. Uvs
+     * are interpolated linearly, and if the mesh had normals, they are
+     * replaced with smooth ones.
+     */
+    pub fn subdivide(&self, method: Subdivision, levels: usize) -> Self {
+        if levels == 0 {
+            return self.clone();
+        }
+
+        let mut result = self.clone();
+        for _ in 0..levels {
+            result = match method {
+                Subdivision::CatmullClark => result.catmull_clark_step(),
+                Subdivision::Loop => result
+                    .triangulate(Triangulation::ShortestDiagonal)
+                    .loop_step(),
+            };
+        }
+
+        if self.has_normals() {
+            result.with_smooth_normals(180.0)
+        } else {
+            result.without_normals()
+        }
+    }
+
+    /**
+     * One level of Catmull-Clark. The new vertices are the moved original
+     * vertices, then one per edge, then one per face.
+     */
+    fn catmull_clark_step(&self) -> Self {
+        let edges = Edges::new(self);
+        let face_points: Vec<Vertex> = self.faces.iter()
+            .map(|face| self.average(face.indices()))
+            .collect();
+
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(
+            self.vertices.len() + edges.len() + self.faces.len());
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            let neighbors = &edges.neighbors[v];
+            let boundary = edges.boundary_neighbors(v);
+            let moved = if !boundary.is_empty() {
+                boundary_vertex(vertex, &boundary, &self.vertices)
+            } else if neighbors.is_empty() {
+                *vertex
+            } else {
+                // (Q + 2R + (n - 3)P) / n, where Q is the average of the
+                // surrounding face points and R is the average of the
+                // midpoints of the edges
+                let n = neighbors.len() as f32;
+                let q = average_of(edges.vertex_faces[v].iter()
+                    .map(|f| face_points[*f]));
+                let r = average_of(neighbors.iter()
+                    .map(|other| vertex.add(&self.vertices[*other]).scale(0.5)));
+                q.add(&r.scale(2.0)).add(&vertex.scale(n - 3.0)).scale(1.0 / n)
+            };
+            vertices.push(moved);
+        }
+
+        for edge in edges.edges.iter() {
+            let [a, b] = edge.ends;
+            let point = match edge.faces[..] {
+                [(f, _), (g, _)] => self.vertices[a]
+                    .add(&self.vertices[b])
+                    .add(&face_points[f])
+                    .add(&face_points[g])
+                    .scale(0.25),
+                _ => self.vertices[a].add(&self.vertices[b]).scale(0.5)
+            };
+            vertices.push(point);
+        }
+
+        let edge_offset = self.vertices.len();
+        let face_offset = edge_offset + edges.len();
+        vertices.extend(face_points);
+
+        let mut result = Self {
+            vertices,
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..self.clone()
+        };
+        let mut uvs = UvSplitter::new(&mut result.uvs);
+        let mut new_faces: Vec<(Face, FaceAttributes)> = Vec::new();
+        let faces = self.faces.iter().zip(self.face_attributes.iter());
+        for (f, (face, attributes)) in faces.enumerate() {
+            let indices = face.indices();
+            let n = indices.len();
+            let face_uvs = attributes.uvs.as_ref().map(|corners| corners.indices());
+            let center_uv = face_uvs.map(|corners| uvs.center(corners));
+
+            // A quad for every corner: the corner, the midpoint of the
+            // edge after it, the center and the midpoint of the edge before
+            for i in 0..n {
+                let previous = (i + n - 1) % n;
+                let next = (i + 1) % n;
+                let new_face = Face::Quad([
+                    indices[i],
+                    edge_offset + edges.index(indices[i], indices[next]),
+                    face_offset + f,
+                    edge_offset + edges.index(indices[previous], indices[i]),
+                ]);
+                let new_uvs = face_uvs.map(|corners| Face::Quad([
+                    corners[i],
+                    uvs.midpoint(corners[i], corners[next]),
+                    center_uv.unwrap(),
+                    uvs.midpoint(corners[previous], corners[i]),
+                ]));
+                new_faces.push((new_face, FaceAttributes {
+                    uvs: new_uvs,
+                    normals: None,
+                    ..attributes.clone()
+                }));
+            }
+        }
+
+        for (face, attributes) in new_faces {
+            result.push_face(face, attributes);
+        }
+
+        result
+    }
+
+    /**
+     * One level of Loop subdivision of a triangle mesh. The new vertices are
+     * the moved original vertices followed by one per edge.
+     */
+    fn loop_step(&self) -> Self {
+        let edges = Edges::new(self);
+
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(
+            self.vertices.len() + edges.len());
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            let neighbors = &edges.neighbors[v];
+            let boundary = edges.boundary_neighbors(v);
+            let moved = if !boundary.is_empty() {
+                boundary_vertex(vertex, &boundary, &self.vertices)
+            } else if neighbors.is_empty() {
+                *vertex
+            } else {
+                // Loop's weights for a vertex with n neighbors
+                let n = neighbors.len() as f32;
+                let cos = (2.0 * std::f32::consts::PI / n).cos();
+                let beta = (0.625 - (0.375 + 0.25 * cos).powi(2)) / n;
+                let sum = neighbors.iter().fold(Vertex([0.0, 0.0, 0.0]),
+                    |sum, other| sum.add(&self.vertices[*other]));
+                vertex.scale(1.0 - n * beta).add(&sum.scale(beta))
+            };
+            vertices.push(moved);
+        }
+
+        for edge in edges.edges.iter() {
+            let [a, b] = edge.ends;
+            let midpoint = self.vertices[a].add(&self.vertices[b]).scale(0.5);
+            let point = match edge.faces[..] {
+                [(_, c), (_, d)] => midpoint.scale(0.75).add(
+                    &self.vertices[c].add(&self.vertices[d]).scale(0.125)),
+                _ => midpoint
+            };
+            vertices.push(point);
+        }
+
+        let edge_offset = self.vertices.len();
+        let mut result = Self {
+            vertices,
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..self.clone()
+        };
+        let mut uvs = UvSplitter::new(&mut result.uvs);
+        let mut new_faces: Vec<(Face, FaceAttributes)> = Vec::new();
+        let faces = self.faces.iter().zip(self.face_attributes.iter());
+        for (face, attributes) in faces {
+            let [a, b, c] = match face.indices() {
+                [a, b, c] => [*a, *b, *c],
+                _ => unreachable!("faces are triangulated before Loop subdivision")
+            };
+            let ab = edge_offset + edges.index(a, b);
+            let bc = edge_offset + edges.index(b, c);
+            let ca = edge_offset + edges.index(c, a);
+
+            let corner_uvs = attributes.uvs.as_ref().map(|corners| {
+                let [ua, ub, uc] = [
+                    corners.indices()[0], corners.indices()[1], corners.indices()[2]
+                ];
+                [ua, ub, uc,
+                    uvs.midpoint(ua, ub), uvs.midpoint(ub, uc), uvs.midpoint(uc, ua)]
+            });
+
+            // A triangle at each corner and one in the middle
+            let triangles = [
+                [0, 3, 5],
+                [1, 4, 3],
+                [2, 5, 4],
+                [3, 4, 5],
+            ];
+            let points = [a, b, c, ab, bc, ca];
+            for [i, j, k] in triangles.iter() {
+                new_faces.push((
+                    Face::Triangle([points[*i], points[*j], points[*k]]),
+                    FaceAttributes {
+                        uvs: corner_uvs.map(|corners| Face::Triangle(
+                            [corners[*i], corners[*j], corners[*k]])),
+                        normals: None,
+                        ..attributes.clone()
+                    }
+                ));
+            }
+        }
+
+        for (face, attributes) in new_faces {
+            result.push_face(face, attributes);
+        }
+
+        result
+    }
+
+    fn average(&self, indices: &[usize]) -> Vertex {
+        average_of(indices.iter().map(|v| self.vertices[*v]))
+    }
+}
+
+fn average_of<I: Iterator<Item = Vertex>>(points: I) -> Vertex {
+    let mut sum = Vertex([0.0, 0.0, 0.0]);
+    let mut count = 0;
+    for point in points {
+        sum = sum.add(&point);
+        count += 1;
+    }
+
+    sum.scale(1.0 / count.max(1) as f32)
+}
+
+/**
+ * Vertices on a boundary follow the cubic B-spline along it, so creases
+ * stay put. Where more than two boundary edges meet, the vertex is a corner
+ * and doesn't move.
+ */
+fn boundary_vertex(vertex: &Vertex, boundary: &[usize], vertices: &[Vertex]) -> Vertex {
+    match boundary {
+        [a, b] => vertex.scale(0.75)
+            .add(&vertices[*a].add(&vertices[*b]).scale(0.125)),
+        _ => *vertex
+    }
+}
+
+struct Edge {
+    ends: [usize; 2],
+    /// Each face using the edge, with the face's vertex opposite the edge
+    /// (only meaningful for triangles)
+    faces: Vec<(usize, usize)>,
+}
+
+/// The edges of a mesh, numbered in the order they are first seen
+struct Edges {
+    edges: Vec<Edge>,
+    lookup: HashMap<[usize; 2], usize>,
+    neighbors: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+impl Edges {
+    fn new(mesh: &Mesh) -> Self {
+        let mut result = Self {
+            edges: Vec::new(),
+            lookup: HashMap::new(),
+            neighbors: vec![Vec::new(); mesh.vertices.len()],
+            vertex_faces: vec![Vec::new(); mesh.vertices.len()],
+        };
+
+        for (f, face) in mesh.faces.iter().enumerate() {
+            let indices = face.indices();
+            let n = indices.len();
+            for (i, a) in indices.iter().enumerate() {
+                let b = indices[(i + 1) % n];
+                let opposite = indices[(i + 2) % n];
+                result.vertex_faces[*a].push(f);
+
+                let key = [*a.min(&b), *a.max(&b)];
+                let edges = &mut result.edges;
+                let neighbors = &mut result.neighbors;
+                let index = *result.lookup.entry(key).or_insert_with(|| {
+                    edges.push(Edge {
+                        ends: key,
+                        faces: Vec::new(),
+                    });
+                    neighbors[key[0]].push(key[1]);
+                    neighbors[key[1]].push(key[0]);
+                    edges.len() - 1
+                });
+                result.edges[index].faces.push((f, opposite));
+            }
+        }
+
+        result
+    }
+
+    fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    fn index(&self, a: usize, b: usize) -> usize {
+        self.lookup[&[a.min(b), a.max(b)]]
+    }
+
+    /// Neighbors of a vertex across edges that don't have exactly 2 faces
+    fn boundary_neighbors(&self, vertex: usize) -> Vec<usize> {
+        self.neighbors[vertex].iter()
+            .copied()
+            .filter(|other| self.edges[self.index(vertex, *other)].faces.len() != 2)
+            .collect()
+    }
+}
+
+/// Adds interpolated uvs to a uv table, sharing midpoints between faces
+struct UvSplitter<'a> {
+    uvs: &'a mut Vec<[f32; 2]>,
+    midpoints: HashMap<[usize; 2], usize>,
+}
+
+impl<'a> UvSplitter<'a> {
+    fn new(uvs: &'a mut Vec<[f32; 2]>) -> Self {
+        Self {
+            uvs,
+            midpoints: HashMap::new(),
+        }
+    }
+
+    fn midpoint(&mut self, a: usize, b: usize) -> usize {
+        let uvs = &mut self.uvs;
+        *self.midpoints.entry([a.min(b), a.max(b)]).or_insert_with(|| {
+            let [u_a, v_a] = uvs[a];
+            let [u_b, v_b] = uvs[b];
+            uvs.push([0.5 * (u_a + u_b), 0.5 * (v_a + v_b)]);
+            uvs.len() - 1
+        })
+    }
+
+    fn center(&mut self, corners: &[usize]) -> usize {
+        let mut sum = [0.0, 0.0];
+        for corner in corners.iter() {
+            sum[0] += self.uvs[*corner][0];
+            sum[1] += self.uvs[*corner][1];
+        }
+
+        let n = corners.len() as f32;
+        self.uvs.push([sum[0] / n, sum[1] / n]);
+        self.uvs.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    #[test]
+    fn catmull_clark_cube_stays_closed() {
+        let subdivided = cube().subdivide(Subdivision::CatmullClark, 2);
+        assert_eq!(subdivided.faces().len(), 6 * 16);
+        assert!(subdivided.faces().iter().all(|face| face.len() == 4));
+        assert!(subdivided.analyze().is_clean());
+
+        // Smoothing only shrinks a convex shape. The limit surface of the
+        // cube encloses about a third of it
+        let volume = subdivided.volume();
+        assert!(volume > 0.3 && volume < 1.0, "volume {}", volume);
+    }
+
+    #[test]
+    fn catmull_clark_moves_corners_by_the_vertex_rule() {
+        // (F + 2R + (n - 3)P) / n with n = 3, F = (1/3, 1/3, 1/3) and
+        // R = (1/6, 1/6, 1/6)
+        let subdivided = cube().subdivide(Subdivision::CatmullClark, 1);
+        let corner = subdivided.vertices()[0];
+        let expected = Vertex([2.0 / 9.0; 3]);
+        assert!(corner.sub(&expected).length() < 1e-6, "corner at {:?}", corner);
+    }
+
+    #[test]
+    fn loop_cube_stays_closed() {
+        let subdivided = cube().subdivide(Subdivision::Loop, 2);
+        assert_eq!(subdivided.faces().len(), 12 * 16);
+        assert!(subdivided.faces().iter().all(|face| face.len() == 3));
+        assert!(subdivided.analyze().is_clean());
+    }
+
+    #[test]
+    fn boundaries_are_kept_as_creases() {
+        // A single square is all boundary. It stays flat, and the new
+        // vertices on its edges are their midpoints
+        let mut square = cube();
+        square.faces.truncate(1);
+        square.face_attributes.truncate(1);
+
+        for method in [Subdivision::CatmullClark, Subdivision::Loop].iter() {
+            let subdivided = square.subdivide(*method, 1);
+            for face in subdivided.faces().iter() {
+                for v in face.indices() {
+                    let Vertex([x, _, _]) = subdivided.vertices()[*v];
+                    assert_eq!(x, 0.0);
+                }
+            }
+            assert!(subdivided.vertices().contains(&Vertex([0.0, 0.0, 0.5])));
+            assert!(!subdivided.analyze().is_watertight());
+        }
+    }
+
+    #[test]
+    fn zero_levels_changes_nothing() {
+        let mesh = cube();
+        assert_eq!(mesh.subdivide(Subdivision::Loop, 0).faces(), mesh.faces());
+    }
+}
@@ -0,0 +1,64 @@
+//! Small meshes for the unit tests, and checks on them
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::Mesh;
+
+/**
+ * The faces of the unit cube as corner numbers, counterclockwise seen from
+ * outside. Corner c is at (c & 1, c >> 1 & 1, c >> 2 & 1).
+ */
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+fn corner(c: usize) -> Vertex {
+    Vertex([(c & 1) as f32, (c >> 1 & 1) as f32, (c >> 2 & 1) as f32])
+}
+
+/// The cube from (0, 0, 0) to (1, 1, 1) as 6 quads facing outward
+pub(crate) fn cube() -> Mesh {
+    let vertices = (0..8).map(corner).collect();
+    let faces = CUBE_FACES.iter().map(|face| Face::Quad(*face)).collect();
+    Mesh::from_geometry(vertices, faces)
+}
+
+/// The same cube with separate vertices for each face, like unwelded tiles
+pub(crate) fn unwelded_cube() -> Mesh {
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut faces: Vec<Face> = Vec::new();
+    for face in CUBE_FACES.iter() {
+        faces.push(Face::Quad([0, 1, 2, 3]).offset(vertices.len()));
+        vertices.extend(face.iter().map(|c| corner(*c)));
+    }
+
+    Mesh::from_geometry(vertices, faces)
+}
+
+/// Whether every edge is used once in each direction, so the mesh is closed
+/// and wound consistently
+pub(crate) fn is_closed(mesh: &Mesh) -> bool {
+    let mut edges: Vec<[usize; 2]> = mesh.faces().iter().flat_map(|face| {
+        let indices = face.indices();
+        (0..indices.len())
+            .map(|i| [indices[i], indices[(i + 1) % indices.len()]])
+            .collect::<Vec<_>>()
+    }).collect();
+    edges.sort_unstable();
+
+    let distinct = edges.windows(2).all(|pair| pair[0] != pair[1]);
+    distinct && edges.iter().all(|[a, b]| edges.binary_search(&[*b, *a]).is_ok())
+}
+
+/// The signed volume of a closed mesh, with faces split into fans
+pub(crate) fn fan_volume(mesh: &Mesh) -> f32 {
+    let triangles = mesh.faces().iter().flat_map(|face| face.fan_triangles());
+    triangles.map(|corners| {
+        let [a, b, c] = corners.map(|v| mesh.vertices()[v]);
+        a.dot(&b.cross(&c)) / 6.0
+    }).sum()
+}
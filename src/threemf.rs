@@ -0,0 +1,223 @@
+use std::io::{self, BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+use std::collections::HashMap;
+
+use crate::primitives::Vertex;
+use crate::mesh::Mesh;
+use crate::parts::Parts;
+use crate::error::MeshError;
+use crate::zip::ZipWriter;
+use crate::color::hex_color;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+impl Mesh {
+    /**
+     * Save the mesh as a 3MF package. Mesh units are grid cells, so
+     * coordinates are scaled by cell_size to get millimeters. Each part
+     * becomes a separate object, and if the parts have colors, each object
+     * gets its own base material.
+     */
+    pub fn save_3mf<P: AsRef<Path>>(
+            &self, fname: P, parts: &Parts, cell_size: f32)
+            -> Result<(), MeshError> {
+        let fname = fname.as_ref().display().to_string();
+        let file = File::create(&fname)
+            .map_err(|error| MeshError::io(&fname, error))?;
+        self.write_3mf(BufWriter::new(file), parts, cell_size)
+            .map_err(|error| MeshError::io(&fname, error))
+    }
+
+    pub fn write_3mf<W: Write>(&self, file: W, parts: &Parts, cell_size: f32)
+            -> io::Result<()> {
+        let model = self.build_3mf_model(parts, cell_size);
+
+        let mut zip = ZipWriter::new(file);
+        zip.add_file("[Content_Types].xml", CONTENT_TYPES.as_bytes())?;
+        zip.add_file("_rels/.rels", RELATIONSHIPS.as_bytes())?;
+        zip.add_file("3D/3dmodel.model", model.as_bytes())?;
+        zip.finish()?;
+
+        Ok(())
+    }
+
+    fn build_3mf_model(&self, parts: &Parts, cell_size: f32) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(concat!(
+            r#"<model unit="millimeter" xml:lang="en-US" "#,
+            r#"xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">"#,
+            "\n"));
+        xml.push_str(&format!(
+            "  <metadata name=\"Description\">Celtic knot, {} mm per cell\
+            </metadata>\n",
+            cell_size));
+        xml.push_str("  <resources>\n");
+
+        // Resource ids must be unique across materials and objects.
+        // The material group takes id 1 if there is one
+        let materials_id = 1;
+        if let Some(colors) = &parts.colors {
+            xml.push_str(&format!(
+                "    <basematerials id=\"{}\">\n", materials_id));
+            for (name, color) in parts.names.iter().zip(colors.iter()) {
+                xml.push_str(&format!(
+                    "      <base name=\"{}\" displaycolor=\"{}\"/>\n",
+                    escape_xml(name), hex_color(color)));
+            }
+            xml.push_str("    </basematerials>\n");
+        }
+
+        let mut object_ids: Vec<usize> = Vec::new();
+        for (part, faces) in parts.faces_per_part().iter().enumerate() {
+            let triangles = self.part_triangles(faces);
+            if triangles.is_empty() {
+                continue;
+            }
+
+            let id = materials_id + 1 + object_ids.len();
+            object_ids.push(id);
+
+            let material = match parts.colors {
+                Some(_) => format!(
+                    " pid=\"{}\" pindex=\"{}\"", materials_id, part),
+                None => String::new()
+            };
+            xml.push_str(&format!(
+                "    <object id=\"{}\" type=\"model\" name=\"{}\"{}>\n",
+                id, escape_xml(&parts.names[part]), material));
+            self.write_3mf_mesh(&mut xml, &triangles, cell_size);
+            xml.push_str("    </object>\n");
+        }
+
+        xml.push_str("  </resources>\n");
+        xml.push_str("  <build>\n");
+        for id in object_ids.iter() {
+            xml.push_str(&format!("    <item objectid=\"{}\"/>\n", id));
+        }
+        xml.push_str("  </build>\n");
+        xml.push_str("</model>\n");
+
+        xml
+    }
+
+    /**
+     * Triangulate the given faces. 3MF does not allow triangles that repeat
+     * a vertex, so those are skipped.
+     */
+    fn part_triangles(&self, faces: &[usize]) -> Vec<[usize; 3]> {
+        faces.iter()
+            .flat_map(|face| self.faces[*face].fan_triangles())
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .collect()
+    }
+
+    /// Write a <mesh> element with only the vertices the triangles use
+    fn write_3mf_mesh(
+            &self, xml: &mut String, triangles: &[[usize; 3]], cell_size: f32) {
+        let mut new_indices: HashMap<usize, usize> = HashMap::new();
+        let mut used: Vec<usize> = Vec::new();
+        for triangle in triangles.iter() {
+            for vertex in triangle.iter() {
+                new_indices.entry(*vertex).or_insert_with(|| {
+                    used.push(*vertex);
+                    used.len() - 1
+                });
+            }
+        }
+
+        xml.push_str("      <mesh>\n        <vertices>\n");
+        for vertex in used.iter() {
+            let Vertex([x, y, z]) = self.vertices[*vertex].scale(cell_size);
+            xml.push_str(&format!(
+                "          <vertex x=\"{}\" y=\"{}\" z=\"{}\"/>\n", x, y, z));
+        }
+        xml.push_str("        </vertices>\n        <triangles>\n");
+        for [a, b, c] in triangles.iter() {
+            xml.push_str(&format!(
+                "          <triangle v1=\"{}\" v2=\"{}\" v3=\"{}\"/>\n",
+                new_indices[a], new_indices[b], new_indices[c]));
+        }
+        xml.push_str("        </triangles>\n      </mesh>\n");
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    #[test]
+    fn model_is_in_millimeters() {
+        let model = cube().build_3mf_model(&Parts::single("knot", 6), 10.0);
+        assert!(model.starts_with("<?xml"));
+        assert!(model.contains(r#"<model unit="millimeter""#));
+        assert_eq!(model.matches("<object ").count(), 1);
+        assert!(model.contains(r#"<object id="2" type="model" name="knot">"#));
+        assert!(model.contains(r#"<item objectid="2"/>"#));
+        assert!(!model.contains("basematerials"));
+
+        // Each quad is split in two, and one cell is 10 mm
+        assert_eq!(model.matches("<vertex ").count(), 8);
+        assert_eq!(model.matches("<triangle ").count(), 12);
+        assert!(model.contains(r#"<vertex x="10" y="10" z="10"/>"#));
+        assert!(model.trim_end().ends_with("</model>"));
+    }
+
+    #[test]
+    fn colored_parts_get_their_own_objects_and_materials() {
+        let mut parts = Parts::from_keys((0..6).map(|f| {
+            String::from(if f < 2 { "red & \"hot\"" } else { "blue" })
+        }));
+        parts.colors = Some(vec![[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        let model = cube().build_3mf_model(&parts, 1.0);
+
+        assert!(model.contains(r#"<basematerials id="1">"#));
+        assert!(model.contains(
+            r##"<base name="red &amp; &quot;hot&quot;" displaycolor="#FF0000"/>"##));
+        assert!(model.contains(r##"<base name="blue" displaycolor="#0000FF"/>"##));
+        assert!(model.contains(concat!(
+            r#"<object id="2" type="model" name="red &amp; &quot;hot&quot;" "#,
+            r#"pid="1" pindex="0">"#)));
+        assert!(model.contains(
+            r#"<object id="3" type="model" name="blue" pid="1" pindex="1">"#));
+        assert!(model.contains(r#"<item objectid="2"/>"#));
+        assert!(model.contains(r#"<item objectid="3"/>"#));
+        assert_eq!(model.matches("<triangle ").count(), 12);
+    }
+
+    #[test]
+    fn package_holds_the_model_and_its_relationships() {
+        let mut bytes: Vec<u8> = Vec::new();
+        cube().write_3mf(&mut bytes, &Parts::single("knot", 6), 10.0).unwrap();
+
+        assert!(bytes.starts_with(b"PK\x03\x04"));
+        let contains = |name: &str| bytes.windows(name.len())
+            .any(|window| window == name.as_bytes());
+        for name in ["[Content_Types].xml", "_rels/.rels", "3D/3dmodel.model"].iter() {
+            assert!(contains(name));
+        }
+        assert!(contains(r#"Target="/3D/3dmodel.model""#));
+        assert!(contains(r#"<model unit="millimeter""#));
+    }
+}
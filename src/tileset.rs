@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use crate::mesh::Mesh;
+use crate::cube_rotations::CubeRotation;
+use crate::error::MeshError;
+
+/// The OBJ files every tileset directory must provide
+pub const TILE_NAMES: [&str; 4] = [
+    "one_twist",
+    "one_corner",
+    "one_edge",
+    "end_cap",
+];
+
+/**
+ * The tiles that make up a knot. Only a few pieces are modeled by hand,
+ * the rest are built by rotating copies of them. These are computed once when
+ * the tileset is loaded rather than for every cell.
+ */
+#[derive(Clone)]
+pub struct Tileset {
+    quad_twist: Mesh,
+    untwist: Mesh,
+    connector: Mesh,
+    end_cap: Mesh,
+    edge_cap: Mesh,
+}
+
+impl Tileset {
+    pub fn new(twist: Mesh, corner: Mesh, edge: Mesh, end_cap: Mesh) -> Self {
+        Self {
+            quad_twist: make_quad_twist(&twist),
+            untwist: make_untwist(&edge),
+            connector: make_connector(&corner),
+            end_cap,
+            edge_cap: edge,
+        }
+    }
+
+    /**
+     * Load a tileset from a directory containing the files listed in
+     * TILE_NAMES
+     */
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, MeshError> {
+        let dir = dir.as_ref();
+        Ok(Self::new(
+            Mesh::from_obj_file(format_path(dir, "one_twist"))?,
+            Mesh::from_obj_file(format_path(dir, "one_corner"))?,
+            Mesh::from_obj_file(format_path(dir, "one_edge"))?,
+            Mesh::from_obj_file(format_path(dir, "end_cap"))?))
+    }
+
+    pub fn quad_twist(&self) -> &Mesh {
+        &self.quad_twist
+    }
+
+    pub fn untwist(&self) -> &Mesh {
+        &self.untwist
+    }
+
+    pub fn connector(&self) -> &Mesh {
+        &self.connector
+    }
+
+    pub fn end_cap(&self) -> &Mesh {
+        &self.end_cap
+    }
+
+    pub fn edge_cap(&self) -> &Mesh {
+        &self.edge_cap
+    }
+}
+
+pub fn format_path(tileset: &Path, obj_name: &str) -> PathBuf {
+    tileset.join(format!("{}.obj", obj_name))
+}
+
+fn make_quad_twist(twist: &Mesh) -> Mesh {
+    let rz = CubeRotation::rz();
+    let twist2 = twist.rotate(&rz);
+
+    let twist3 = twist2.rotate(&rz);
+    let twist4 = twist3.rotate(&rz);
+
+    let mut result = Mesh::new();
+    result.add_geometry(twist);
+    result.add_geometry(&twist2);
+    result.add_geometry(&twist3);
+    result.add_geometry(&twist4);
+
+    result
+}
+
+fn make_connector(corner: &Mesh) -> Mesh {
+    let rx = CubeRotation::rx();
+    let rx2 = &rx * &rx;
+    let rz = CubeRotation::rz();
+    let rz2 = &rz * &rz;
+
+    let mut result = Mesh::new();
+    result.add_geometry(corner);
+
+    let one_rotated = corner.rotate(&rx);
+    result.add_geometry(&one_rotated);
+
+    let two_rotated = result.rotate(&rx2);
+    result.add_geometry(&two_rotated);
+
+    let four_rotated = result.rotate(&rz2);
+    result.add_geometry(&four_rotated);
+
+    result
+}
+
+fn make_untwist(edge: &Mesh) -> Mesh {
+    let mut result = edge.clone();
+
+    let rz = CubeRotation::rz();
+    let rz2 = CubeRotation::rz2();
+
+    let rotated1 = result.rotate(&rz);
+    result.add_geometry(&rotated1);
+
+    let rotated2 = result.rotate(&rz2);
+    result.add_geometry(&rotated2);
+
+    result
+}
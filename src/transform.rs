@@ -0,0 +1,300 @@
+use std::ops::Mul;
+
+use crate::primitives::{Vertex, Face};
+use crate::mesh::Mesh;
+use crate::cube_rotations::CubeRotation;
+
+/**
+ * An affine transform, stored as the top 3 rows of a 4x4 matrix that acts
+ * on column vectors. The last column is the translation.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    rows: [[f32; 4]; 3],
+}
+
+impl Transform {
+    /// Build a transform from the top 3 rows of a 4x4 matrix
+    pub fn from_rows(rows: [[f32; 4]; 3]) -> Self {
+        Self {
+            rows
+        }
+    }
+
+    /**
+     * Build a transform from a full 4x4 matrix. Returns None if the bottom
+     * row is not [0, 0, 0, 1], since then it isn't affine.
+     */
+    pub fn from_matrix(matrix: [[f32; 4]; 4]) -> Option<Self> {
+        if matrix[3] != [0.0, 0.0, 0.0, 1.0] {
+            return None;
+        }
+
+        Some(Self::from_rows([matrix[0], matrix[1], matrix[2]]))
+    }
+
+    pub fn identity() -> Self {
+        Self::scale(1.0, 1.0, 1.0)
+    }
+
+    pub fn translation(dx: f32, dy: f32, dz: f32) -> Self {
+        Self::from_rows([
+            [1.0, 0.0, 0.0, dx],
+            [0.0, 1.0, 0.0, dy],
+            [0.0, 0.0, 1.0, dz],
+        ])
+    }
+
+    /// Scale along each axis. Negative factors mirror the mesh
+    pub fn scale(sx: f32, sy: f32, sz: f32) -> Self {
+        Self::from_rows([
+            [sx, 0.0, 0.0, 0.0],
+            [0.0, sy, 0.0, 0.0],
+            [0.0, 0.0, sz, 0.0],
+        ])
+    }
+
+    pub fn uniform_scale(factor: f32) -> Self {
+        Self::scale(factor, factor, factor)
+    }
+
+    /// Rotate counterclockwise around the given axis, angle in radians
+    pub fn rotation(axis: &Vertex, angle: f32) -> Self {
+        let Vertex([x, y, z]) = axis.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+
+        Self::from_rows([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+        ])
+    }
+
+    pub fn rotation_x(angle: f32) -> Self {
+        Self::rotation(&Vertex([1.0, 0.0, 0.0]), angle)
+    }
+
+    pub fn rotation_y(angle: f32) -> Self {
+        Self::rotation(&Vertex([0.0, 1.0, 0.0]), angle)
+    }
+
+    pub fn rotation_z(angle: f32) -> Self {
+        Self::rotation(&Vertex([0.0, 0.0, 1.0]), angle)
+    }
+
+    /// Mirror across the plane through the origin with the given normal
+    pub fn reflection(normal: &Vertex) -> Self {
+        let Vertex(n) = normal.normalize();
+        let mut rows = [[0.0; 4]; 3];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                let identity = if i == j { 1.0 } else { 0.0 };
+                *value = identity - 2.0 * n[i] * n[j];
+            }
+        }
+
+        Self::from_rows(rows)
+    }
+
+    /**
+     * The determinant of the linear part. It is negative if the transform
+     * mirrors, which turns faces inside out.
+     */
+    pub fn determinant(&self) -> f32 {
+        let m = &self.rows;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Transform a point, including the translation
+    pub fn apply_point(&self, point: &Vertex) -> Vertex {
+        let Vertex([x, y, z]) = point;
+        let mut result = [0.0; 3];
+        for (value, row) in result.iter_mut().zip(self.rows.iter()) {
+            *value = row[0] * x + row[1] * y + row[2] * z + row[3];
+        }
+
+        Vertex(result)
+    }
+
+    /// Transform a direction, which ignores the translation
+    pub fn apply_vector(&self, vector: &Vertex) -> Vertex {
+        let Vertex([x, y, z]) = vector;
+        let mut result = [0.0; 3];
+        for (value, row) in result.iter_mut().zip(self.rows.iter()) {
+            *value = row[0] * x + row[1] * y + row[2] * z;
+        }
+
+        Vertex(result)
+    }
+
+    /**
+     * Transform a surface normal. Normals stay perpendicular to the
+     * surface under the inverse transpose of the linear part rather than
+     * the matrix itself. That is proportional to the cofactor matrix, which
+     * avoids dividing by the determinant.
+     */
+    pub fn apply_normal(&self, normal: &Vertex) -> Vertex {
+        let m = &self.rows;
+        let column = |j: usize| Vertex([m[0][j], m[1][j], m[2][j]]);
+        let (a, b, c) = (column(0), column(1), column(2));
+
+        // The columns of the cofactor matrix are the cross products of
+        // the columns of the matrix
+        let Vertex([x, y, z]) = normal;
+        let result = b.cross(&c).scale(*x)
+            .add(&c.cross(&a).scale(*y))
+            .add(&a.cross(&b).scale(*z));
+
+        let sign = if self.determinant() < 0.0 { -1.0 } else { 1.0 };
+        result.scale(sign).normalize()
+    }
+}
+
+impl From<&CubeRotation> for Transform {
+    fn from(rotation: &CubeRotation) -> Self {
+        let columns = [
+            rotation * &Vertex([1.0, 0.0, 0.0]),
+            rotation * &Vertex([0.0, 1.0, 0.0]),
+            rotation * &Vertex([0.0, 0.0, 1.0]),
+        ];
+
+        let mut rows = [[0.0; 4]; 3];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (value, Vertex(column)) in row.iter_mut().zip(columns.iter()) {
+                *value = column[i];
+            }
+        }
+
+        Self::from_rows(rows)
+    }
+}
+
+/// self * other applies other first, then self
+impl Mul<&Transform> for &Transform {
+    type Output = Transform;
+    fn mul(self, other: &Transform) -> Transform {
+        // Columns of the other matrix, including the implicit bottom row
+        let column = |j: usize| [
+            other.rows[0][j],
+            other.rows[1][j],
+            other.rows[2][j],
+            if j == 3 { 1.0 } else { 0.0 },
+        ];
+
+        let mut rows = [[0.0; 4]; 3];
+        for (row, self_row) in rows.iter_mut().zip(self.rows.iter()) {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self_row.iter().zip(column(j).iter())
+                    .map(|(a, b)| a * b)
+                    .sum();
+            }
+        }
+
+        Transform::from_rows(rows)
+    }
+}
+
+impl Mul<&Vertex> for &Transform {
+    type Output = Vertex;
+    fn mul(self, other: &Vertex) -> Vertex {
+        self.apply_point(other)
+    }
+}
+
+impl Mesh {
+    /**
+     * Apply an affine transform to the vertices and normals. If the
+     * transform mirrors the mesh, every face is rewound so it still faces
+     * outward.
+     */
+    pub fn transform(&self, transform: &Transform) -> Self {
+        let mut result = Self {
+            vertices: self.vertices.iter()
+                .map(|vertex| transform.apply_point(vertex))
+                .collect(),
+            normals: self.normals.iter()
+                .map(|normal| transform.apply_normal(normal))
+                .collect(),
+            ..self.clone()
+        };
+
+        if transform.determinant() < 0.0 {
+            for (face, attributes) in result.faces.iter_mut()
+                    .zip(result.face_attributes.iter_mut()) {
+                *face = face.reversed();
+                attributes.uvs = attributes.uvs.as_ref().map(Face::reversed);
+                attributes.normals =
+                    attributes.normals.as_ref().map(Face::reversed);
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::cube;
+
+    fn assert_close(actual: &Vertex, expected: &Vertex) {
+        assert!(actual.sub(expected).length() < 1e-5,
+            "expected {:?}, found {:?}", expected, actual);
+    }
+
+    #[test]
+    fn products_apply_the_right_transform_first() {
+        let translation = Transform::translation(1.0, 0.0, 0.0);
+        let combined = &translation * &Transform::uniform_scale(2.0);
+        let point = &combined * &Vertex([1.0, 1.0, 1.0]);
+        assert_close(&point, &Vertex([3.0, 2.0, 2.0]));
+
+        let rotation = Transform::rotation_z(std::f32::consts::FRAC_PI_2);
+        let x = rotation.apply_point(&Vertex([1.0, 0.0, 0.0]));
+        assert_close(&x, &Vertex([0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn only_affine_matrices_are_accepted() {
+        let mut matrix = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        assert_eq!(Transform::from_matrix(matrix), Some(Transform::identity()));
+
+        matrix[3][0] = 0.5;
+        assert_eq!(Transform::from_matrix(matrix), None);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_uneven_scaling() {
+        let transform = Transform::scale(2.0, 1.0, 1.0);
+        let normal = Vertex([1.0, 1.0, 0.0]).normalize();
+        let tangent = Vertex([1.0, -1.0, 0.0]);
+
+        let new_normal = transform.apply_normal(&normal);
+        let new_tangent = transform.apply_vector(&tangent);
+        assert!(new_normal.dot(&new_tangent).abs() < 1e-6);
+        assert!((new_normal.length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mirrored_cube_still_faces_outward() {
+        let mirror = Transform::reflection(&Vertex([1.0, 0.0, 0.0]));
+        assert!(mirror.determinant() < 0.0);
+
+        let mirrored = cube().with_flat_normals().transform(&mirror);
+        assert!((mirrored.volume() - 1.0).abs() < 1e-5);
+        assert!(mirrored.analyze().is_clean());
+        for (f, attributes) in mirrored.face_attributes().iter().enumerate() {
+            for n in attributes.normals.as_ref().unwrap().indices() {
+                assert_close(&mirrored.normals()[*n], &mirrored.face_normal(f));
+            }
+        }
+    }
+}
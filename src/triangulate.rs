@@ -0,0 +1,251 @@
+use crate::primitives::{Vertex, Face};
+use crate::mesh::{Mesh, FaceAttributes};
+
+/// How to split faces with more than 3 corners into triangles
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Triangulation {
+    /// A fan around the first corner, like the exporters use
+    Fan,
+    /// Split quads along their shorter diagonal, and fan larger polygons
+    ShortestDiagonal,
+    /// Ear clipping, which also handles concave polygons
+    EarClipping,
+}
+
+impl Mesh {
+    /**
+     * Split every face into triangles. The triangles keep the winding order
+     * of the original face, and the per-face attributes (including uv and
+     * normal corners) are copied to each of them.
+     */
+    pub fn triangulate(&self, method: Triangulation) -> Self {
+        let mut result = Self {
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..self.clone()
+        };
+
+        let faces = self.faces.iter().zip(self.face_attributes.iter());
+        for (f, (face, attributes)) in faces.enumerate() {
+            if let Face::Triangle(_) = face {
+                result.push_face(face.clone(), attributes.clone());
+                continue;
+            }
+
+            let corners = match (method, face) {
+                (Triangulation::Fan, _) => fan_corners(face.len()),
+                (_, Face::Quad(_)) => self.split_quad(face),
+                (Triangulation::ShortestDiagonal, _) => fan_corners(face.len()),
+                (Triangulation::EarClipping, _)
+                    => clip_ears(&self.vertices, face.indices(), &self.face_normal(f)),
+            };
+
+            for [a, b, c] in corners {
+                let pick = |face: &Face| {
+                    let indices = face.indices();
+                    Face::Triangle([indices[a], indices[b], indices[c]])
+                };
+                result.push_face(pick(face), FaceAttributes {
+                    uvs: attributes.uvs.as_ref().map(pick),
+                    normals: attributes.normals.as_ref().map(pick),
+                    ..attributes.clone()
+                });
+            }
+        }
+
+        result
+    }
+
+    /**
+     * Split a quad along its shorter diagonal. Nonplanar quads are folded
+     * along the diagonal, and the shorter one keeps the fold shallow. If
+     * the quad is concave, only the diagonal through the reflex corner
+     * gives two triangles facing the same way as the quad, so that one is
+     * always used.
+     */
+    fn split_quad(&self, face: &Face) -> Vec<[usize; 3]> {
+        let indices = face.indices();
+        let position = |corner: usize| self.vertices[indices[corner]];
+        let from_0 = [[0, 1, 2], [0, 2, 3]];
+        let from_1 = [[1, 2, 3], [1, 3, 0]];
+
+        let facing = |triangles: &[[usize; 3]; 2]| {
+            let normals: Vec<Vertex> = triangles.iter()
+                .map(|[a, b, c]| position(*b).sub(&position(*a))
+                     .cross(&position(*c).sub(&position(*a))))
+                .collect();
+            normals[0].dot(&normals[1]) > 0.0
+        };
+        let convex_0 = facing(&from_0);
+        let convex_1 = facing(&from_1);
+        if convex_0 != convex_1 {
+            return if convex_0 { from_0.to_vec() } else { from_1.to_vec() };
+        }
+
+        let diagonal_0 = position(2).sub(&position(0)).length();
+        let diagonal_1 = position(3).sub(&position(1)).length();
+        if diagonal_0 <= diagonal_1 {
+            from_0.to_vec()
+        } else {
+            from_1.to_vec()
+        }
+    }
+}
+
+fn fan_corners(count: usize) -> Vec<[usize; 3]> {
+    (1..count - 1).map(|i| [0, i, i + 1]).collect()
+}
+
+/**
+ * Ear clipping of a polygon in the plane with the given normal. Repeatedly
+ * cut off a convex corner whose triangle has no other corners inside it.
+ * If no such corner is left (e.g. the polygon is degenerate), the rest is
+ * fanned. The triangles are numbered by corner and wound like the polygon.
+ */
+pub(crate) fn clip_ears(vertices: &[Vertex], corners: &[usize], normal: &Vertex)
+        -> Vec<[usize; 3]> {
+    let points = project(vertices, corners, normal);
+
+    let mut remaining: Vec<usize> = (0..corners.len()).collect();
+    let mut triangles: Vec<[usize; 3]> = Vec::new();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|i| {
+            let a = remaining[(i + n - 1) % n];
+            let b = remaining[*i];
+            let c = remaining[(i + 1) % n];
+            if cross_2d(points[a], points[b], points[c]) <= 0.0 {
+                return false;
+            }
+
+            remaining.iter()
+                .filter(|other| ![a, b, c].contains(other))
+                .all(|other| {
+                    !inside_triangle(points[*other], points[a], points[b], points[c])
+                })
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + n - 1) % n],
+                    remaining[i],
+                    remaining[(i + 1) % n]
+                ]);
+                remaining.remove(i);
+            },
+            None => break
+        }
+    }
+
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+
+    triangles
+}
+
+/**
+ * Coordinates of the corners in the plane with the given normal,
+ * counterclockwise seen from the side the normal points to
+ */
+fn project(vertices: &[Vertex], corners: &[usize], normal: &Vertex) -> Vec<[f32; 2]> {
+    let Vertex([x, _, _]) = normal;
+
+    // Any direction not parallel to the normal will do for the first axis
+    let helper = if x.abs() < 0.9 {
+        Vertex([1.0, 0.0, 0.0])
+    } else {
+        Vertex([0.0, 1.0, 0.0])
+    };
+    let u = helper.cross(normal).normalize();
+    let v = normal.cross(&u);
+
+    corners.iter().map(|vertex| {
+        let position = &vertices[*vertex];
+        [position.dot(&u), position.dot(&v)]
+    }).collect()
+}
+
+/// Twice the signed area of the triangle abc, positive if counterclockwise
+fn cross_2d(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn inside_triangle(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+    cross_2d(a, b, p) >= 0.0 && cross_2d(b, c, p) >= 0.0 && cross_2d(c, a, p) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::{cube, is_closed};
+
+    /// A flat polygon in the xy plane, counterclockwise seen from above
+    fn polygon(corners: &[[f32; 2]]) -> Mesh {
+        let vertices = corners.iter().map(|[x, y]| Vertex([*x, *y, 0.0])).collect();
+        let face = Face::from_indices((0..corners.len()).collect());
+        Mesh::from_geometry(vertices, vec![face])
+    }
+
+    fn assert_facing_up(mesh: &Mesh) {
+        for f in 0..mesh.faces().len() {
+            let Vertex([_, _, z]) = mesh.face_normal(f);
+            assert!(z > 0.0, "face {:?} is flipped", mesh.faces()[f]);
+        }
+    }
+
+    fn total_area(mesh: &Mesh) -> f32 {
+        mesh.faces().iter().map(|face| {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices()[face.indices()[i]]);
+            0.5 * b.sub(&a).cross(&c.sub(&a)).length()
+        }).sum()
+    }
+
+    #[test]
+    fn ear_clipping_handles_concave_polygons() {
+        // An L shape starting next to its reflex corner, where a fan would
+        // make a flipped triangle
+        let mesh = polygon(&[
+            [2.0, 1.0], [1.0, 1.0], [1.0, 2.0], [0.0, 2.0], [0.0, 0.0], [2.0, 0.0]
+        ]);
+
+        let triangulated = mesh.triangulate(Triangulation::EarClipping);
+        assert_eq!(triangulated.faces().len(), 4);
+        assert_facing_up(&triangulated);
+        assert!((total_area(&triangulated) - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn concave_quads_are_split_through_the_reflex_corner() {
+        // The diagonal from corner 0 is shorter, but runs outside the quad
+        let mesh = polygon(&[[0.0, 0.0], [1.0, 0.9], [2.0, 0.0], [1.0, 3.0]]);
+        let methods = [Triangulation::ShortestDiagonal, Triangulation::EarClipping];
+        for method in methods.iter() {
+            let triangulated = mesh.triangulate(*method);
+            assert_eq!(triangulated.faces().len(), 2);
+            assert_facing_up(&triangulated);
+        }
+    }
+
+    #[test]
+    fn quads_are_split_along_the_shorter_diagonal() {
+        let mesh = polygon(&[[0.0, 0.0], [3.0, 0.0], [4.0, 1.0], [1.0, 1.0]]);
+        let triangulated = mesh.triangulate(Triangulation::ShortestDiagonal);
+        assert_eq!(triangulated.faces(), &[
+            Face::Triangle([1, 2, 3]), Face::Triangle([1, 3, 0])
+        ]);
+    }
+
+    #[test]
+    fn triangulating_keeps_a_closed_mesh_closed() {
+        for method in [
+                Triangulation::Fan,
+                Triangulation::ShortestDiagonal,
+                Triangulation::EarClipping].iter() {
+            let triangulated = cube().triangulate(*method);
+            assert_eq!(triangulated.faces().len(), 12);
+            assert!(is_closed(&triangulated));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::primitives::Vertex;
+use crate::mesh::{Mesh, remove_repeated_corners};
+
+/// A good default for grids, where tile coordinates are multiples of 1/8
+pub const DEFAULT_WELD_TOLERANCE: f32 = 1e-4;
+
+impl Mesh {
+    /**
+     * Merge vertices that are within tolerance of each other, so tiles that
+     * meet at a seam share vertices and form connected surfaces. Faces are
+     * remapped to the merged vertices, and faces that collapse to fewer than
+     * 3 distinct vertices are removed.
+     *
+     * Vertices are bucketed in a spatial hash with cells the size of the
+     * tolerance, so each vertex only needs to be compared against its
+     * neighbors.
+     */
+    pub fn weld(&self, tolerance: f32) -> Self {
+        // With cells twice the tolerance, the box around a vertex overlaps
+        // at most 2 cells along each axis
+        let cell_size = 2.0 * tolerance.max(f32::EPSILON);
+        let mut buckets: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        let mut new_vertices: Vec<Vertex> = Vec::new();
+        let mut new_indices: Vec<usize> = Vec::with_capacity(self.vertices.len());
+
+        for vertex in self.vertices.iter() {
+            let Vertex(components) = vertex;
+            let mut min_cell = [0i64; 3];
+            let mut max_cell = [0i64; 3];
+            for axis in 0..3 {
+                min_cell[axis] = hash_coordinate(components[axis] - tolerance, cell_size);
+                max_cell[axis] = hash_coordinate(components[axis] + tolerance, cell_size);
+            }
+
+            let mut existing: Option<usize> = None;
+            'search: for x in min_cell[0]..=max_cell[0] {
+                for y in min_cell[1]..=max_cell[1] {
+                    for z in min_cell[2]..=max_cell[2] {
+                        let candidates = match buckets.get(&[x, y, z]) {
+                            Some(candidates) => candidates,
+                            None => continue
+                        };
+                        existing = candidates.iter().copied().find(|i| {
+                            new_vertices[*i].sub(vertex).length() <= tolerance
+                        });
+                        if existing.is_some() {
+                            break 'search;
+                        }
+                    }
+                }
+            }
+
+            let index = match existing {
+                Some(index) => index,
+                None => {
+                    new_vertices.push(*vertex);
+                    let index = new_vertices.len() - 1;
+                    let cell = [
+                        hash_coordinate(components[0], cell_size),
+                        hash_coordinate(components[1], cell_size),
+                        hash_coordinate(components[2], cell_size),
+                    ];
+                    buckets.entry(cell).or_default().push(index);
+                    index
+                }
+            };
+            new_indices.push(index);
+        }
+
+        let mut result = Self {
+            vertices: new_vertices,
+            faces: Vec::new(),
+            face_attributes: Vec::new(),
+            ..self.clone()
+        };
+        let faces = self.faces.iter().zip(self.face_attributes.iter());
+        for (face, attributes) in faces {
+            let remapped = face.map(|v| new_indices[v]);
+            if let Some((new_face, new_attributes)) =
+                    remove_repeated_corners(&remapped, attributes) {
+                result.push_face(new_face, new_attributes);
+            }
+        }
+
+        result
+    }
+}
+
+fn hash_coordinate(value: f32, cell_size: f32) -> i64 {
+    (value / cell_size).floor() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::Face;
+    use crate::test_meshes::{cube, unwelded_cube};
+
+    /// How many edges only one face uses
+    fn open_edges(mesh: &Mesh) -> usize {
+        let mut counts: HashMap<[usize; 2], usize> = HashMap::new();
+        for face in mesh.faces() {
+            let indices = face.indices();
+            for (i, start) in indices.iter().enumerate() {
+                let end = indices[(i + 1) % indices.len()];
+                *counts.entry([*start.min(&end), *start.max(&end)]).or_insert(0) += 1;
+            }
+        }
+
+        counts.values().filter(|count| **count == 1).count()
+    }
+
+    #[test]
+    fn welding_tiles_closes_the_seams() {
+        let unwelded = unwelded_cube();
+        assert_eq!(open_edges(&unwelded), 24);
+
+        let welded = unwelded.weld(DEFAULT_WELD_TOLERANCE);
+        assert_eq!(welded.vertices().len(), 8);
+        assert_eq!(welded.faces().len(), 6);
+        assert_eq!(open_edges(&welded), 0);
+    }
+
+    #[test]
+    fn vertices_further_apart_than_the_tolerance_stay_apart() {
+        let mesh = Mesh::from_geometry(
+            vec![
+                Vertex([0.0, 0.0, 0.0]),
+                Vertex([1.0, 0.0, 0.0]),
+                Vertex([0.0, 1.0, 0.0]),
+                Vertex([0.0, 0.0, 0.01]),
+            ],
+            vec![Face::Triangle([0, 1, 2]), Face::Triangle([3, 2, 1])]);
+
+        assert_eq!(mesh.weld(0.001).vertices().len(), 4);
+        assert_eq!(mesh.weld(0.02).vertices().len(), 3);
+    }
+
+    #[test]
+    fn collapsed_edges_drop_a_corner() {
+        let mut mesh = cube();
+        mesh.vertices[1] = Vertex([0.0, 0.0, 0.000_01]);
+
+        // The two quads along the collapsed edge become triangles
+        let welded = mesh.weld(DEFAULT_WELD_TOLERANCE);
+        assert_eq!(welded.vertices().len(), 7);
+        assert_eq!(welded.faces().len(), 6);
+        let triangles = welded.faces().iter().filter(|face| face.len() == 3).count();
+        assert_eq!(triangles, 2);
+    }
+
+    #[test]
+    fn collapsed_triangles_are_removed() {
+        let mesh = Mesh::from_geometry(
+            vec![
+                Vertex([0.0, 0.0, 0.0]),
+                Vertex([0.000_01, 0.0, 0.0]),
+                Vertex([0.0, 1.0, 0.0]),
+                Vertex([1.0, 1.0, 0.0]),
+            ],
+            vec![Face::Triangle([0, 1, 2]), Face::Triangle([0, 3, 2])]);
+
+        let welded = mesh.weld(DEFAULT_WELD_TOLERANCE);
+        assert_eq!(welded.faces(), &[Face::Triangle([0, 2, 1])]);
+    }
+}
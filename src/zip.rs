@@ -0,0 +1,217 @@
+use std::io::{self, Write};
+use std::convert::TryFrom;
+
+/**
+ * Minimal writer for zip archives, enough for container formats like 3MF.
+ * Entries are stored without compression.
+ */
+pub struct ZipWriter<W: Write> {
+    writer: W,
+    offset: u32,
+    entries: Vec<ZipEntry>,
+}
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+// MS-DOS date for 1980-01-01, the earliest date zip can represent
+const DOS_DATE: u16 = (1 << 5) | 1;
+const DOS_TIME: u16 = 0;
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /**
+     * Add a file to the archive. Without ZIP64, entries and the whole
+     * archive are limited to 4 GB, which gives an InvalidInput error.
+     */
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| too_large("zip entries are limited to 4 GB"))?;
+        let name_length = u16::try_from(name.len())
+            .map_err(|_| too_large("zip entry names are limited to 65535 bytes"))?;
+
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc: crc32(data),
+            size,
+            offset: self.offset,
+        };
+
+        let mut header: Vec<u8> = Vec::new();
+        push_u32(&mut header, 0x0403_4b50);
+        push_u16(&mut header, 20); // version needed to extract
+        push_u16(&mut header, 0); // flags
+        push_u16(&mut header, 0); // stored, no compression
+        push_u16(&mut header, DOS_TIME);
+        push_u16(&mut header, DOS_DATE);
+        push_u32(&mut header, entry.crc);
+        push_u32(&mut header, entry.size);
+        push_u32(&mut header, entry.size);
+        push_u16(&mut header, name_length);
+        push_u16(&mut header, 0); // extra field length
+        header.extend_from_slice(name.as_bytes());
+
+        let end = u32::try_from(header.len() + data.len()).ok()
+            .and_then(|length| self.offset.checked_add(length))
+            .ok_or_else(|| too_large("zip archives are limited to 4 GB"))?;
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        self.offset = end;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Write the central directory. This must be called last.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut directory: Vec<u8> = Vec::new();
+        for entry in self.entries.iter() {
+            push_u32(&mut directory, 0x0201_4b50);
+            push_u16(&mut directory, 20); // version made by
+            push_u16(&mut directory, 20); // version needed to extract
+            push_u16(&mut directory, 0); // flags
+            push_u16(&mut directory, 0); // stored, no compression
+            push_u16(&mut directory, DOS_TIME);
+            push_u16(&mut directory, DOS_DATE);
+            push_u32(&mut directory, entry.crc);
+            push_u32(&mut directory, entry.size);
+            push_u32(&mut directory, entry.size);
+            push_u16(&mut directory, entry.name.len() as u16);
+            push_u16(&mut directory, 0); // extra field length
+            push_u16(&mut directory, 0); // comment length
+            push_u16(&mut directory, 0); // disk number
+            push_u16(&mut directory, 0); // internal attributes
+            push_u32(&mut directory, 0); // external attributes
+            push_u32(&mut directory, entry.offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let count = u16::try_from(self.entries.len())
+            .map_err(|_| too_large("zip archives are limited to 65535 entries"))?;
+        let directory_size = u32::try_from(directory.len())
+            .map_err(|_| too_large("zip archives are limited to 4 GB"))?;
+        push_u32(&mut directory, 0x0605_4b50);
+        push_u16(&mut directory, 0); // this disk
+        push_u16(&mut directory, 0); // disk with the directory
+        push_u16(&mut directory, count);
+        push_u16(&mut directory, count);
+        push_u32(&mut directory, directory_size);
+        push_u32(&mut directory, self.offset);
+        push_u16(&mut directory, 0); // comment length
+
+        self.writer.write_all(&directory)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn too_large(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// CRC-32 as used by zip (IEEE polynomial, reflected)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data.iter() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> usize {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as usize
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn local_headers_and_central_directory_agree() {
+        let files: [(&str, &[u8]); 3] = [
+            ("first.txt", b"hello"),
+            ("dir/second.bin", &[0, 1, 2, 255]),
+            ("empty", b""),
+        ];
+        let mut zip = ZipWriter::new(Vec::new());
+        for (name, data) in files.iter() {
+            zip.add_file(name, data).unwrap();
+        }
+        let bytes = zip.finish().unwrap();
+
+        // The end of central directory record is the last 22 bytes, since
+        // there's no comment
+        let end = bytes.len() - 22;
+        assert_eq!(u32_at(&bytes, end), 0x0605_4b50);
+        assert_eq!(u16_at(&bytes, end + 8), files.len());
+        assert_eq!(u16_at(&bytes, end + 10), files.len());
+        let directory_size = u32_at(&bytes, end + 12) as usize;
+        let directory_offset = u32_at(&bytes, end + 16) as usize;
+        assert_eq!(directory_offset + directory_size, end);
+
+        let mut local = 0;
+        let mut central = directory_offset;
+        for (name, data) in files.iter() {
+            // Local header, followed by the name and the stored data
+            assert_eq!(u32_at(&bytes, local), 0x0403_4b50);
+            assert_eq!(u16_at(&bytes, local + 8), 0);
+            assert_eq!(u32_at(&bytes, local + 14), crc32(data));
+            assert_eq!(u32_at(&bytes, local + 18) as usize, data.len());
+            assert_eq!(u32_at(&bytes, local + 22) as usize, data.len());
+            let name_length = u16_at(&bytes, local + 26);
+            let data_start = local + 30 + name_length + u16_at(&bytes, local + 28);
+            assert_eq!(&bytes[local + 30..local + 30 + name_length], name.as_bytes());
+            assert_eq!(&bytes[data_start..data_start + data.len()], *data);
+
+            // The directory entry points back at the local header
+            assert_eq!(u32_at(&bytes, central), 0x0201_4b50);
+            assert_eq!(u32_at(&bytes, central + 16), crc32(data));
+            assert_eq!(u32_at(&bytes, central + 24) as usize, data.len());
+            assert_eq!(u32_at(&bytes, central + 42) as usize, local);
+            let name_length = u16_at(&bytes, central + 28);
+            assert_eq!(&bytes[central + 46..central + 46 + name_length], name.as_bytes());
+
+            local = data_start + data.len();
+            central += 46 + name_length;
+        }
+        assert_eq!(local, directory_offset);
+        assert_eq!(central, end);
+    }
+}